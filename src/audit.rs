@@ -0,0 +1,274 @@
+use serenity::builder::{CreateEmbed, CreateMessage};
+use serenity::http::Http;
+use serenity::model::prelude::*;
+
+use rusqlite::{Connection, params};
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::config::Config;
+use crate::verification::unix_now;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Approve,
+    Deny,
+    RemoveUser,
+    ConfigChange,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 4] = [
+        Self::Approve,
+        Self::Deny,
+        Self::RemoveUser,
+        Self::ConfigChange,
+    ];
+
+    fn title(&self) -> &'static str {
+        match self {
+            Self::Approve => "Verification approved",
+            Self::Deny => "Verification denied",
+            Self::RemoveUser => "User removed",
+            Self::ConfigChange => "Config changed",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Self::Approve => write!(f, "approve"),
+            Self::Deny => write!(f, "deny"),
+            Self::RemoveUser => write!(f, "remove_user"),
+            Self::ConfigChange => write!(f, "config"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseAuditActionError;
+
+impl FromStr for AuditAction {
+    type Err = ParseAuditActionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "approve" => Ok(Self::Approve),
+            "deny" => Ok(Self::Deny),
+            "remove_user" => Ok(Self::RemoveUser),
+            "config" => Ok(Self::ConfigChange),
+            _ => Err(ParseAuditActionError),
+        }
+    }
+}
+
+/// A single membership or config action taken by an admin.
+pub struct AuditEntry {
+    pub actor: UserId,
+    pub target: Option<UserId>,
+    pub action: AuditAction,
+    pub before: Option<String>,
+    pub after: Option<String>,
+    pub created_at: u64,
+}
+
+impl AuditEntry {
+    pub fn new(actor: UserId, action: AuditAction) -> Self {
+        Self {
+            actor,
+            target: None,
+            action,
+            before: None,
+            after: None,
+            created_at: unix_now(),
+        }
+    }
+
+    pub fn target(mut self, target: UserId) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn before(mut self, before: impl Into<String>) -> Self {
+        self.before = Some(before.into());
+        self
+    }
+
+    pub fn after(mut self, after: impl Into<String>) -> Self {
+        self.after = Some(after.into());
+        self
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let conn = Connection::open("users.db").map_err(|_| "Database failure")?;
+
+        conn.execute(
+            "INSERT INTO audit_log (actor_id, target_id, action, before, after, created_at)
+             values (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                self.actor.get(),
+                self.target.map(|target| target.get()),
+                self.action.to_string(),
+                self.before,
+                self.after,
+                self.created_at,
+            ],
+        )
+        .map_err(|e| format!("Could not save audit entry: {e}"))?;
+
+        Ok(())
+    }
+
+    pub fn create_embed(&self) -> CreateEmbed {
+        let mut embed = CreateEmbed::new()
+            .title(self.action.title())
+            .field("Actor", format!("<@{}>", self.actor), true)
+            .timestamp(
+                Timestamp::from_unix_timestamp(self.created_at as i64)
+                    .unwrap_or_else(|_| Timestamp::now()),
+            )
+            .color(0xff66f0);
+
+        if let Some(target) = self.target {
+            embed = embed.field("Member", format!("<@{target}>"), true);
+        }
+
+        if let Some(ref before) = self.before {
+            embed = embed.field("Before", truncate_field(before), false);
+        }
+
+        if let Some(ref after) = self.after {
+            embed = embed.field("After", truncate_field(after), false);
+        }
+
+        embed
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "actor": self.actor.to_string(),
+            "target": self.target.map(|target| target.to_string()),
+            "action": self.action.to_string(),
+            "before": self.before,
+            "after": self.after,
+            "created_at": self.created_at,
+        })
+    }
+
+    /// Stores the entry and posts it to the log channel if one is configured.
+    pub async fn record(self, http: &Http) {
+        if let Err(e) = self.save() {
+            eprintln!("{e}");
+        }
+
+        let log_channel = Config::load().unwrap_or_default().channels.log_channel;
+        if let Some(log_channel) = log_channel
+            && let Err(e) = log_channel
+                .send_message(http, CreateMessage::new().embed(self.create_embed()))
+                .await
+        {
+            eprintln!("Could not post audit entry: {e}");
+        }
+    }
+}
+
+/// Which audit entries to return, every field is optional.
+#[derive(Default)]
+pub struct AuditFilter {
+    pub member: Option<UserId>,
+    pub actor: Option<UserId>,
+    pub action: Option<AuditAction>,
+    /// Inclusive unix timestamp bounds.
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+}
+
+const FILTER_CLAUSE: &str = "(?1 IS NULL OR target_id=?1)
+    AND (?2 IS NULL OR actor_id=?2)
+    AND (?3 IS NULL OR action=?3)
+    AND (?4 IS NULL OR created_at>=?4)
+    AND (?5 IS NULL OR created_at<=?5)";
+
+type FilterParams = (
+    Option<u64>,
+    Option<u64>,
+    Option<String>,
+    Option<u64>,
+    Option<u64>,
+);
+
+impl AuditFilter {
+    fn params(&self) -> FilterParams {
+        (
+            self.member.map(|member| member.get()),
+            self.actor.map(|actor| actor.get()),
+            self.action.map(|action| action.to_string()),
+            self.from,
+            self.to,
+        )
+    }
+
+    pub fn count(&self) -> Result<u32, String> {
+        let conn = Connection::open("users.db").map_err(|_| "Database failure")?;
+
+        conn.query_row(
+            &format!("SELECT COUNT(*) FROM audit_log WHERE {FILTER_CLAUSE}"),
+            self.params(),
+            |row| row.get(0),
+        )
+        .map_err(|_| "Database failure".to_string())
+    }
+
+    /// Matching entries, newest first.
+    pub fn query(&self, limit: u32, offset: u32) -> Result<Vec<AuditEntry>, String> {
+        let conn = Connection::open("users.db").map_err(|_| "Database failure")?;
+
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT actor_id, target_id, action, before, after, created_at
+                FROM audit_log
+                WHERE {FILTER_CLAUSE}
+                ORDER BY created_at DESC, id DESC
+                LIMIT {limit} OFFSET {offset}"
+            ))
+            .map_err(|_| "Database failure")?;
+
+        let rows = stmt
+            .query_map(self.params(), |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, Option<u64>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, u64>(5)?,
+                ))
+            })
+            .map_err(|_| "Database failure")?;
+
+        Ok(rows
+            .flatten()
+            .filter_map(|(actor, target, action, before, after, created_at)| {
+                Some(AuditEntry {
+                    actor: UserId::new(actor),
+                    target: target.map(UserId::new),
+                    action: AuditAction::from_str(&action).ok()?,
+                    before,
+                    after,
+                    created_at,
+                })
+            })
+            .collect())
+    }
+}
+
+// embed field values are limited to 1024 characters
+fn truncate_field(value: &str) -> String {
+    if value.chars().count() <= 1024 {
+        return value.to_string();
+    }
+
+    value.chars().take(1021).collect::<String>() + "..."
+}
@@ -0,0 +1,183 @@
+use serenity::builder::{
+    CreateAttachment, CreateButton, CreateCommand, CreateCommandOption,
+    CreateInteractionResponseMessage,
+};
+use serenity::model::prelude::*;
+
+use std::str::FromStr;
+
+use crate::audit::{AuditAction, AuditFilter};
+
+const PAGE_SIZE: u32 = 5;
+const EXPORT_LIMIT: u32 = 10_000;
+
+pub fn register() -> CreateCommand {
+    let member = CreateCommandOption::new(6.into(), "member", "Only entries about this member");
+    let actor = CreateCommandOption::new(6.into(), "actor", "Only entries by this admin");
+
+    let mut action = CreateCommandOption::new(3.into(), "action", "Only entries of this type");
+    for choice in AuditAction::ALL {
+        action = action.add_string_choice(choice.to_string(), choice.to_string());
+    }
+
+    let from = CreateCommandOption::new(3.into(), "from", "Earliest day to include, YYYY-MM-DD");
+    let to = CreateCommandOption::new(3.into(), "to", "Latest day to include, YYYY-MM-DD");
+
+    CreateCommand::new("audit")
+        .description("Search the audit log")
+        .default_member_permissions(Permissions::ADMINISTRATOR)
+        .add_option(member)
+        .add_option(actor)
+        .add_option(action)
+        .add_option(from)
+        .add_option(to)
+}
+
+/// Unix timestamp of midnight UTC on a `YYYY-MM-DD` date.
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.trim().split('-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+
+    let leap_year = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap_year => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+
+    if parts.next().is_some() || !(1..=days_in_month).contains(&day) {
+        return None;
+    }
+
+    // days_from_civil from Howard Hinnant's date algorithms
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    u64::try_from(days * 86_400).ok()
+}
+
+pub fn execute(cmd_data: &CommandData) -> Result<CreateInteractionResponseMessage, String> {
+    let mut filter = AuditFilter::default();
+
+    for option in cmd_data.options() {
+        match (option.name, option.value) {
+            ("member", ResolvedValue::User(user, _)) => filter.member = Some(user.id),
+            ("actor", ResolvedValue::User(user, _)) => filter.actor = Some(user.id),
+            ("action", ResolvedValue::String(action)) => {
+                filter.action = Some(
+                    AuditAction::from_str(action)
+                        .map_err(|_| format!("{action} is not a valid action"))?,
+                )
+            }
+            ("from", ResolvedValue::String(date)) => {
+                filter.from = Some(parse_date(date).ok_or(format!("{date} is not a valid date"))?)
+            }
+            ("to", ResolvedValue::String(date)) => {
+                // the whole day is included
+                filter.to =
+                    Some(parse_date(date).ok_or(format!("{date} is not a valid date"))? + 86_399)
+            }
+            _ => continue,
+        }
+    }
+
+    let export = filter
+        .query(EXPORT_LIMIT, 0)?
+        .iter()
+        .map(|entry| entry.to_json())
+        .collect::<Vec<_>>();
+    let export = serde_json::to_vec_pretty(&export).map_err(|e| e.to_string())?;
+
+    Ok(page(&filter, 0)?.add_file(CreateAttachment::bytes(export, "audit.json")))
+}
+
+/// Handles the previous/next buttons, whose ids are
+/// `audit <page> <member> <actor> <action> <from> <to>` with `-` for unset filters.
+pub fn change_page(id: &[&str]) -> Result<CreateInteractionResponseMessage, String> {
+    let field = |index: usize| id.get(index).filter(|value| **value != "-");
+
+    let page_index = field(1)
+        .and_then(|page| page.parse::<u32>().ok())
+        .ok_or("Invalid audit id")?;
+
+    let filter = AuditFilter {
+        member: field(2).and_then(|id| id.parse().ok()).map(UserId::new),
+        actor: field(3).and_then(|id| id.parse().ok()).map(UserId::new),
+        action: field(4).and_then(|action| AuditAction::from_str(action).ok()),
+        from: field(5).and_then(|from| from.parse().ok()),
+        to: field(6).and_then(|to| to.parse().ok()),
+    };
+
+    page(&filter, page_index)
+}
+
+fn page(filter: &AuditFilter, page: u32) -> Result<CreateInteractionResponseMessage, String> {
+    let total = filter.count()?;
+    let page_count = total.div_ceil(PAGE_SIZE).max(1);
+    let page = page.min(page_count - 1);
+
+    let entries = filter.query(PAGE_SIZE, page * PAGE_SIZE)?;
+
+    let content = if entries.is_empty() {
+        "No audit entries match these filters".to_string()
+    } else {
+        format!("{total} entries, page {}/{page_count}", page + 1)
+    };
+
+    let or_unset = |value: Option<String>| value.unwrap_or("-".to_string());
+    let id = |page: u32| {
+        format!(
+            "audit {page} {} {} {} {} {}",
+            or_unset(filter.member.map(|member| member.to_string())),
+            or_unset(filter.actor.map(|actor| actor.to_string())),
+            or_unset(filter.action.map(|action| action.to_string())),
+            or_unset(filter.from.map(|from| from.to_string())),
+            or_unset(filter.to.map(|to| to.to_string())),
+        )
+    };
+
+    let previous = CreateButton::new(id(page.saturating_sub(1)))
+        .label("Previous")
+        .disabled(page == 0);
+    let next = CreateButton::new(id(page + 1))
+        .label("Next")
+        .disabled(page + 1 >= page_count);
+
+    Ok(CreateInteractionResponseMessage::new()
+        .content(content)
+        .embeds(entries.iter().map(|entry| entry.create_embed()).collect())
+        .button(previous)
+        .button(next)
+        .ephemeral(true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_date;
+
+    #[test]
+    fn parses_valid_dates() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("2024-03-01"), Some(1_709_251_200));
+        assert_eq!(parse_date("2024-02-29"), Some(1_709_164_800));
+    }
+
+    #[test]
+    fn rejects_days_past_the_end_of_the_month() {
+        assert_eq!(parse_date("2026-02-31"), None);
+        assert_eq!(parse_date("2023-02-29"), None);
+        assert_eq!(parse_date("1900-02-29"), None);
+        assert_eq!(parse_date("2026-04-31"), None);
+        assert_eq!(parse_date("2026-13-01"), None);
+        assert_eq!(parse_date("2026-01-00"), None);
+        assert_eq!(parse_date("2026-01-01-01"), None);
+    }
+}
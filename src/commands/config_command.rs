@@ -1,7 +1,16 @@
 use serenity::builder::{CreateCommand, CreateCommandOption};
 use serenity::model::prelude::*;
 
-use crate::config::Config;
+use serenity::prelude::*;
+
+use serde_json::{Map, Value};
+
+use crate::audit::{AuditAction, AuditEntry};
+use crate::config::{Config, RankRole};
+use crate::game_api::{TACHI_PLAYTYPES, TachiPlaytype};
+use crate::user::Game;
+
+use std::str::FromStr;
 
 pub fn register() -> CreateCommand {
     let set_channel = CreateCommandOption::new(
@@ -18,6 +27,11 @@ pub fn register() -> CreateCommand {
         7.into(),
         "verifications",
         "set the verifications channel",
+    ))
+    .add_sub_option(CreateCommandOption::new(
+        7.into(),
+        "log",
+        "set the channel audit log entries are posted in",
     ));
 
     let add_emoji_exception =
@@ -62,23 +76,165 @@ pub fn register() -> CreateCommand {
         "the role to edit",
     ));
 
-    let config_command = CreateCommand::new("config")
+    let set_verification_ttl = CreateCommandOption::new(
+        1.into(),
+        "set_verification_ttl",
+        "Set how long verification requests stay open",
+    )
+    .add_sub_option(
+        CreateCommandOption::new(4.into(), "hours", "hours before a request expires")
+            .min_int_value(1)
+            .required(true),
+    );
+
+    let set_ownership_challenge = CreateCommandOption::new(
+        1.into(),
+        "set_ownership_challenge",
+        "Require users to prove they own the profile they verify with",
+    )
+    .add_sub_option(
+        CreateCommandOption::new(5.into(), "enabled", "whether the challenge is required")
+            .required(true),
+    );
+
+    let set_rank_refresh_interval = CreateCommandOption::new(
+        1.into(),
+        "set_rank_refresh_interval",
+        "Set how often the ranks of linked accounts are refreshed",
+    )
+    .add_sub_option(
+        CreateCommandOption::new(4.into(), "hours", "hours between refreshes")
+            .min_int_value(1)
+            .required(true),
+    );
+
+    let set_prefix_commands = CreateCommandOption::new(
+        1.into(),
+        "set_prefix_commands",
+        "Also answer the old !verify and !list messages",
+    )
+    .add_sub_option(
+        CreateCommandOption::new(5.into(), "enabled", "whether prefix commands are answered")
+            .required(true),
+    );
+
+    let mut game =
+        CreateCommandOption::new(3.into(), "game", "the game ranks are taken from").required(true);
+    for choice in Game::ALL {
+        game = game.add_string_choice(choice.title(), choice.to_string());
+    }
+
+    let add_rank_role = CreateCommandOption::new(
+        1.into(),
+        "add_rank_role",
+        "Give members ranked at or above a global rank in a game a role",
+    )
+    .add_sub_option(game.clone())
+    .add_sub_option(
+        CreateCommandOption::new(4.into(), "max_rank", "the worst rank in the bracket")
+            .min_int_value(1)
+            .required(true),
+    )
+    .add_sub_option(CreateCommandOption::new(8.into(), "role", "the bracket role").required(true))
+    .add_sub_option(tachi_playtypes(CreateCommandOption::new(
+        3.into(),
+        "playtype",
+        "the playtype ranks are taken from, required for Tachi",
+    )));
+
+    let remove_rank_role = CreateCommandOption::new(
+        1.into(),
+        "remove_rank_role",
+        "Stop using a role as a rank bracket",
+    )
+    .add_sub_option(game)
+    .add_sub_option(CreateCommandOption::new(8.into(), "role", "the bracket role").required(true));
+
+    let set_nickname_template = CreateCommandOption::new(
+        1.into(),
+        "set_nickname_template",
+        "Set the nickname given to verified members",
+    )
+    .add_sub_option(
+        CreateCommandOption::new(
+            3.into(),
+            "template",
+            "can use {username}, {country}, {country_code}, {game} and {rank}",
+        )
+        .required(true),
+    );
+
+    let set_keep_nicknames = CreateCommandOption::new(
+        1.into(),
+        "set_keep_nicknames",
+        "Leave the nicknames of verified members alone",
+    )
+    .add_sub_option(
+        CreateCommandOption::new(5.into(), "enabled", "whether nicknames are left alone")
+            .required(true),
+    );
+
+    let set_member_role = CreateCommandOption::new(
+        1.into(),
+        "set_member_role",
+        "Set the role every verified member gets",
+    )
+    .add_sub_option(CreateCommandOption::new(8.into(), "role", "the member role").required(true));
+
+    let add_approval_role = CreateCommandOption::new(
+        1.into(),
+        "add_approval_role",
+        "Also give a role to members when they are first verified",
+    )
+    .add_sub_option(CreateCommandOption::new(8.into(), "role", "the role to give").required(true));
+
+    let remove_approval_role = CreateCommandOption::new(
+        1.into(),
+        "remove_approval_role",
+        "Stop giving a role to members when they are first verified",
+    )
+    .add_sub_option(
+        CreateCommandOption::new(8.into(), "role", "the role to stop giving").required(true),
+    );
+
+    let playtype = tachi_playtypes(
+        CreateCommandOption::new(3.into(), "playtype", "the tachi game and playtype")
+            .required(true),
+    );
+
+    let set_tachi_playtype = CreateCommandOption::new(
+        1.into(),
+        "set_tachi_playtype",
+        "Set the Tachi playtype accounts are verified for when none is given",
+    )
+    .add_sub_option(playtype);
+
+    CreateCommand::new("config")
         .description("Set the bot config")
         .default_member_permissions(Permissions::ADMINISTRATOR)
         .add_option(add_emoji_exception)
         .add_option(remove_emoji_exception)
         .add_option(set_channel)
         .add_option(add_non_country_role)
-        .add_option(remove_non_country_role);
-
-    config_command
+        .add_option(remove_non_country_role)
+        .add_option(set_verification_ttl)
+        .add_option(set_ownership_challenge)
+        .add_option(set_rank_refresh_interval)
+        .add_option(set_prefix_commands)
+        .add_option(add_rank_role)
+        .add_option(remove_rank_role)
+        .add_option(set_nickname_template)
+        .add_option(set_keep_nicknames)
+        .add_option(set_member_role)
+        .add_option(add_approval_role)
+        .add_option(remove_approval_role)
+        .add_option(set_tachi_playtype)
 }
 
-pub async fn execute(cmd_data: &CommandData) -> String {
-    let mut config = match Config::load() {
-        Some(config) => config,
-        None => Config::default(),
-    };
+pub async fn execute(ctx: &Context, command: &CommandInteraction) -> String {
+    let cmd_data = &command.data;
+    let mut config = Config::load().unwrap_or_default();
+    let before = serde_json::to_value(&config).unwrap_or_default();
 
     let mut response_buf = String::new();
 
@@ -97,12 +253,50 @@ pub async fn execute(cmd_data: &CommandData) -> String {
             "remove_non_country_role" => {
                 remove_non_country_role(&mut config, &option.value, &mut response_buf)
             }
+            "set_verification_ttl" => {
+                set_verification_ttl(&mut config, &option.value, &mut response_buf)
+            }
+            "set_ownership_challenge" => {
+                set_ownership_challenge(&mut config, &option.value, &mut response_buf)
+            }
+            "set_rank_refresh_interval" => {
+                set_rank_refresh_interval(&mut config, &option.value, &mut response_buf)
+            }
+            "set_prefix_commands" => {
+                set_prefix_commands(&mut config, &option.value, &mut response_buf)
+            }
+            "add_rank_role" => add_rank_role(&mut config, &option.value, &mut response_buf),
+            "remove_rank_role" => remove_rank_role(&mut config, &option.value, &mut response_buf),
+            "set_nickname_template" => {
+                set_nickname_template(&mut config, &option.value, &mut response_buf)
+            }
+            "set_keep_nicknames" => {
+                set_keep_nicknames(&mut config, &option.value, &mut response_buf)
+            }
+            "set_member_role" => set_member_role(&mut config, &option.value, &mut response_buf),
+            "add_approval_role" => add_approval_role(&mut config, &option.value, &mut response_buf),
+            "remove_approval_role" => {
+                remove_approval_role(&mut config, &option.value, &mut response_buf)
+            }
+            "set_tachi_playtype" => {
+                set_tachi_playtype(&mut config, &option.value, &mut response_buf)
+            }
             _ => response_buf += format!("{} is not a valid option", option.name).as_str(),
         }
     }
 
     if let Err(e) = config.save() {
         response_buf += &e;
+        return response_buf;
+    }
+
+    let after = serde_json::to_value(&config).unwrap_or_default();
+    if let Some((before, after)) = changed_values(&before, &after) {
+        AuditEntry::new(command.user.id, AuditAction::ConfigChange)
+            .before(before)
+            .after(after)
+            .record(&ctx.http)
+            .await;
     }
 
     response_buf
@@ -174,6 +368,286 @@ fn remove_emoji_exception(
     }
 }
 
+fn set_verification_ttl(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let hours = if let ResolvedValue::Integer(hours) = scmds[0].value {
+            hours
+        } else {
+            return;
+        };
+
+        config.verification_ttl_hours = Some(hours as u64);
+        *response_buf += format!("Verification requests now expire after {hours}h").as_str();
+    }
+}
+
+/// The top level config values that differ between `before` and `after`, as json.
+fn changed_values(before: &Value, after: &Value) -> Option<(String, String)> {
+    let (Value::Object(before), Value::Object(after)) = (before, after) else {
+        return None;
+    };
+
+    let mut changed_before = Map::new();
+    let mut changed_after = Map::new();
+    for (key, value) in after.iter() {
+        let old = before.get(key).cloned().unwrap_or(Value::Null);
+        if old != *value {
+            changed_before.insert(key.clone(), old);
+            changed_after.insert(key.clone(), value.clone());
+        }
+    }
+
+    if changed_after.is_empty() {
+        return None;
+    }
+
+    Some((
+        Value::Object(changed_before).to_string(),
+        Value::Object(changed_after).to_string(),
+    ))
+}
+
+fn set_prefix_commands(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let enabled = if let ResolvedValue::Boolean(enabled) = scmds[0].value {
+            enabled
+        } else {
+            return;
+        };
+
+        config.prefix_commands = enabled;
+        *response_buf += if enabled {
+            "Enabled !verify and !list, restart the bot if they were disabled at startup"
+        } else {
+            "Disabled !verify and !list, use /verify and /list instead"
+        };
+    }
+}
+
+fn set_rank_refresh_interval(
+    config: &mut Config,
+    cmd_value: &ResolvedValue,
+    response_buf: &mut String,
+) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let hours = if let ResolvedValue::Integer(hours) = scmds[0].value {
+            hours
+        } else {
+            return;
+        };
+
+        config.rank_refresh_hours = Some(hours as u64);
+        *response_buf +=
+            format!("Ranks will be refreshed every {hours}h, starting after the next refresh")
+                .as_str();
+    }
+}
+
+fn set_ownership_challenge(
+    config: &mut Config,
+    cmd_value: &ResolvedValue,
+    response_buf: &mut String,
+) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let enabled = if let ResolvedValue::Boolean(enabled) = scmds[0].value {
+            enabled
+        } else {
+            return;
+        };
+
+        config.ownership_challenge = enabled;
+        *response_buf += if enabled {
+            "Users now have to prove they own the profile they verify with"
+        } else {
+            "Users no longer have to prove they own the profile they verify with"
+        };
+    }
+}
+
+fn add_rank_role(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let (
+            ResolvedValue::String(game),
+            ResolvedValue::Integer(max_rank),
+            ResolvedValue::Role(role),
+        ) = (&scmds[0].value, &scmds[1].value, &scmds[2].value)
+        else {
+            return;
+        };
+
+        let Ok(game) = Game::from_str(game) else {
+            *response_buf += format!("{game} is not a valid game").as_str();
+            return;
+        };
+
+        // tachi playtypes are ranked separately, so their brackets are too
+        let variant = match scmds.get(3).map(|scmd| &scmd.value) {
+            Some(ResolvedValue::String(key)) if game == Game::BMS => {
+                let Some(playtype) = TachiPlaytype::get(key) else {
+                    *response_buf += format!("{key} is not a valid playtype").as_str();
+                    return;
+                };
+                Some(playtype.key)
+            }
+            None if game == Game::BMS => {
+                *response_buf += "Tachi rank roles need a playtype";
+                return;
+            }
+            Some(_) => {
+                *response_buf += format!("{} has no playtypes", game.title()).as_str();
+                return;
+            }
+            None => None,
+        };
+
+        let brackets = config.rank_roles.entry(game.ranking(variant)).or_default();
+        brackets.retain(|bracket| bracket.role != role.id);
+        brackets.push(RankRole {
+            max_rank: *max_rank as u32,
+            role: role.id,
+        });
+
+        *response_buf += format!(
+            "{} is now given to {} players ranked #{max_rank} or better, starting after the next rank refresh",
+            role.name,
+            game.variant_title(variant)
+        )
+        .as_str();
+    }
+}
+
+fn remove_rank_role(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let (ResolvedValue::String(game), ResolvedValue::Role(role)) =
+            (&scmds[0].value, &scmds[1].value)
+        else {
+            return;
+        };
+
+        // covers the brackets of every variant of the game
+        let variant_prefix = format!("{game}/");
+        let mut removed = false;
+        for (ranking, brackets) in config.rank_roles.iter_mut() {
+            if ranking != game && !ranking.starts_with(&variant_prefix) {
+                continue;
+            }
+
+            let count = brackets.len();
+            brackets.retain(|bracket| bracket.role != role.id);
+            removed |= brackets.len() < count;
+        }
+
+        *response_buf += if removed {
+            "Removed role from the rank roles, members keep it until it is removed by hand"
+        } else {
+            "Role was not a rank role"
+        };
+    }
+}
+
+fn set_nickname_template(
+    config: &mut Config,
+    cmd_value: &ResolvedValue,
+    response_buf: &mut String,
+) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let template = if let ResolvedValue::String(template) = scmds[0].value {
+            template
+        } else {
+            return;
+        };
+
+        if !template.contains("{username}") {
+            *response_buf += "The template has to contain {username}";
+            return;
+        }
+
+        config.nickname_template = Some(template.to_string());
+        *response_buf += format!("Nicknames of newly verified members now use {template}").as_str();
+    }
+}
+
+fn set_keep_nicknames(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let enabled = if let ResolvedValue::Boolean(enabled) = scmds[0].value {
+            enabled
+        } else {
+            return;
+        };
+
+        config.keep_nicknames = enabled;
+        *response_buf += if enabled {
+            "Nicknames are no longer changed on verification"
+        } else {
+            "Nicknames are set from the nickname template on verification"
+        };
+    }
+}
+
+fn set_member_role(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let role = if let ResolvedValue::Role(role) = scmds[0].value {
+            role
+        } else {
+            return;
+        };
+
+        config.member_role = Some(role.id);
+        *response_buf += format!("Verified members now get {}", role.name).as_str();
+    }
+}
+
+fn add_approval_role(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let role = if let ResolvedValue::Role(role) = scmds[0].value {
+            role
+        } else {
+            return;
+        };
+
+        config.approval_roles.insert(role.id);
+        *response_buf += format!("Members now also get {} when first verified", role.name).as_str();
+    }
+}
+
+fn remove_approval_role(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let role = if let ResolvedValue::Role(role) = scmds[0].value {
+            role
+        } else {
+            return;
+        };
+
+        *response_buf += if config.approval_roles.remove(&role.id) {
+            "Removed role from the approval roles"
+        } else {
+            "Role was not an approval role"
+        };
+    }
+}
+
+fn set_tachi_playtype(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
+    if let ResolvedValue::SubCommand(scmds) = cmd_value {
+        let key = if let ResolvedValue::String(key) = scmds[0].value {
+            key
+        } else {
+            return;
+        };
+
+        let Some(playtype) = TachiPlaytype::get(key) else {
+            *response_buf += format!("{key} is not a supported playtype").as_str();
+            return;
+        };
+
+        config.tachi_playtype = Some(playtype.key.to_string());
+        *response_buf += format!(
+            "Tachi accounts are now verified for {} unless another playtype is given",
+            playtype.name
+        )
+        .as_str();
+    }
+}
+
 fn set_channel(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mut String) {
     if let ResolvedValue::SubCommand(scmds) = cmd_value {
         for scmd in scmds.iter() {
@@ -195,9 +669,24 @@ fn set_channel(config: &mut Config, cmd_value: &ResolvedValue, response_buf: &mu
                         )
                         .as_str();
                     }
+                    "log" => {
+                        config.channels.log_channel = Some(channel.id);
+                        *response_buf += format!(
+                            "Set log channel to {}\n",
+                            channel.name.clone().unwrap_or(channel.id.to_string())
+                        )
+                        .as_str();
+                    }
                     _ => continue,
                 }
             }
         }
     }
 }
+
+fn tachi_playtypes(mut option: CreateCommandOption) -> CreateCommandOption {
+    for choice in TACHI_PLAYTYPES {
+        option = option.add_string_choice(choice.name, choice.key);
+    }
+    option
+}
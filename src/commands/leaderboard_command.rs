@@ -0,0 +1,207 @@
+use serenity::builder::{
+    CreateButton, CreateCommand, CreateCommandOption, CreateEmbed, CreateEmbedFooter,
+    CreateInteractionResponseMessage,
+};
+use serenity::model::prelude::*;
+
+use rusqlite::{Connection, params};
+
+use std::str::FromStr;
+
+use crate::game_api::{TACHI_PLAYTYPES, TachiPlaytype};
+use crate::user::Game;
+
+const PAGE_SIZE: u32 = 10;
+
+pub fn register() -> CreateCommand {
+    let mut game =
+        CreateCommandOption::new(3.into(), "game", "The game to rank members by").required(true);
+    for choice in Game::ALL {
+        game = game.add_string_choice(choice.title(), choice.to_string());
+    }
+
+    let country = CreateCommandOption::new(
+        3.into(),
+        "country",
+        "Only show members from this country (name or code)",
+    );
+
+    let mut playtype = CreateCommandOption::new(
+        3.into(),
+        "playtype",
+        "The Tachi playtype to rank members by, the configured one by default",
+    );
+    for choice in TACHI_PLAYTYPES {
+        playtype = playtype.add_string_choice(choice.name, choice.key);
+    }
+
+    CreateCommand::new("leaderboard")
+        .description("Show the verified members with the best global rank in a game")
+        .add_option(game)
+        .add_option(country)
+        .add_option(playtype)
+}
+
+pub fn execute(cmd_data: &CommandData) -> Result<CreateInteractionResponseMessage, String> {
+    let mut game = None;
+    let mut country = None;
+    let mut playtype = None;
+
+    for option in cmd_data.options() {
+        match (option.name, option.value) {
+            ("game", ResolvedValue::String(value)) => {
+                game = Some(
+                    Game::from_str(value).map_err(|_| format!("{value} is not a valid game"))?,
+                )
+            }
+            ("country", ResolvedValue::String(value)) => {
+                country = Some(
+                    crate::code_from_country(value)
+                        .ok_or(format!("{value} is not a valid country"))?,
+                )
+            }
+            ("playtype", ResolvedValue::String(value)) => {
+                playtype = Some(
+                    TachiPlaytype::get(value).ok_or(format!("{value} is not a valid playtype"))?,
+                )
+            }
+            _ => continue,
+        }
+    }
+
+    let game = game.ok_or("Options were not inputted correctly")?;
+
+    // ranks of different tachi playtypes can't be compared, so only one is shown at a time
+    let variant = match (game, playtype) {
+        (Game::BMS, playtype) => Some(playtype.unwrap_or_else(TachiPlaytype::configured).key),
+        (_, Some(_)) => return Err(format!("{} has no playtypes", game.title())),
+        (_, None) => None,
+    };
+
+    page(game, variant, country, 0)
+}
+
+/// Handles the previous/next buttons, whose ids are
+/// `leaderboard <game> <page> <country code or -> <playtype or ->`.
+pub fn change_page(id: &[&str]) -> Result<CreateInteractionResponseMessage, String> {
+    let game = id
+        .get(1)
+        .and_then(|game| Game::from_str(game).ok())
+        .ok_or("Invalid leaderboard id")?;
+    let page_index = id
+        .get(2)
+        .and_then(|page| page.parse::<u32>().ok())
+        .ok_or("Invalid leaderboard id")?;
+    let country = match id.get(3) {
+        Some(&"-") | None => None,
+        Some(code) => crate::code_from_country(code),
+    };
+    let variant = match id.get(4) {
+        Some(&"-") => None,
+        Some(key) => Some(TachiPlaytype::get(key).ok_or("Invalid leaderboard id")?.key),
+        // buttons from before the playtype was part of the id
+        None => (game == Game::BMS).then(|| TachiPlaytype::configured().key),
+    };
+
+    page(game, variant, country, page_index)
+}
+
+fn page(
+    game: Game,
+    variant: Option<&str>,
+    country_code: Option<&str>,
+    page: u32,
+) -> Result<CreateInteractionResponseMessage, String> {
+    let country = country_code.and_then(crate::country_from_code);
+
+    let conn = Connection::open("users.db").map_err(|_| "Database failure")?;
+
+    let total: u32 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM users
+            WHERE game=?1 AND variant IS ?3 AND active=1 AND global_rank IS NOT NULL
+                AND (?2 IS NULL OR country=?2)",
+            params![game.to_string(), country, variant],
+            |row| row.get(0),
+        )
+        .map_err(|_| "Database failure")?;
+
+    let page_count = total.div_ceil(PAGE_SIZE).max(1);
+    let page = page.min(page_count - 1);
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT discord_id, username, global_rank FROM users
+            WHERE game=?1 AND variant IS ?5 AND active=1 AND global_rank IS NOT NULL
+                AND (?2 IS NULL OR country=?2)
+            ORDER BY global_rank ASC
+            LIMIT ?3 OFFSET ?4",
+        )
+        .map_err(|_| "Database failure")?;
+
+    let rows = stmt
+        .query_map(
+            params![
+                game.to_string(),
+                country,
+                PAGE_SIZE,
+                page * PAGE_SIZE,
+                variant
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, u64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, u32>(2)?,
+                ))
+            },
+        )
+        .map_err(|_| "Database failure")?;
+
+    let mut buf = String::new();
+    for (position, (discord_id, username, rank)) in rows.flatten().enumerate() {
+        buf += format!(
+            "**{}.** <@{discord_id}> ({username}): #{rank}\n",
+            page * PAGE_SIZE + position as u32 + 1
+        )
+        .as_str();
+    }
+
+    if buf.is_empty() {
+        buf = "No ranked members yet".to_string();
+    }
+
+    let title = match country {
+        Some(country) => format!("{} leaderboard for {country}", game.variant_title(variant)),
+        None => format!("{} leaderboard", game.variant_title(variant)),
+    };
+
+    let embed = CreateEmbed::new()
+        .title(title)
+        .description(buf)
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{page_count}",
+            page + 1
+        )))
+        .color(0xff66f0);
+
+    let id = |page: u32| {
+        format!(
+            "leaderboard {game} {page} {} {}",
+            country_code.unwrap_or("-"),
+            variant.unwrap_or("-")
+        )
+    };
+
+    let previous = CreateButton::new(id(page.saturating_sub(1)))
+        .label("Previous")
+        .disabled(page == 0);
+    let next = CreateButton::new(id(page + 1))
+        .label("Next")
+        .disabled(page + 1 >= page_count);
+
+    Ok(CreateInteractionResponseMessage::new()
+        .embed(embed)
+        .button(previous)
+        .button(next))
+}
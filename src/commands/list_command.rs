@@ -1,4 +1,7 @@
-use serenity::builder::{CreateEmbed, CreateMessage};
+use serenity::builder::{
+    CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseMessage,
+    CreateMessage,
+};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 
@@ -11,10 +14,11 @@ fn get_game_counts_buf() -> Result<String, String> {
 
     let mut stmt = conn
         .prepare(
-            "SELECT COUNT(discord_id), game
+            "SELECT COUNT(DISTINCT discord_id), game
             FROM users 
+            WHERE active=1
             GROUP BY game
-            ORDER BY COUNT(discord_id) DESC;",
+            ORDER BY COUNT(DISTINCT discord_id) DESC;",
         )
         .map_err(|_| "Database failure")?;
 
@@ -24,15 +28,13 @@ fn get_game_counts_buf() -> Result<String, String> {
 
     let mut buf = String::new();
 
-    for row in rows.take(10) {
-        if let Ok(row) = row {
-            buf += format!(
-                "**{}**: {}\n",
-                row.1.unwrap_or("Undefined Game".to_string()),
-                row.0.unwrap_or(0)
-            )
-            .as_str();
-        }
+    for row in rows.take(10).flatten() {
+        buf += format!(
+            "**{}**: {}\n",
+            row.1.unwrap_or("Undefined Game".to_string()),
+            row.0.unwrap_or(0)
+        )
+        .as_str();
     }
 
     Ok(buf)
@@ -42,10 +44,11 @@ fn get_country_counts_buf() -> Result<String, String> {
 
     let mut stmt = conn
         .prepare(
-            "SELECT COUNT(discord_id), country 
+            "SELECT COUNT(DISTINCT discord_id), country 
             FROM users 
+            WHERE active=1
             GROUP BY country 
-            ORDER BY COUNT(discord_id) DESC;",
+            ORDER BY COUNT(DISTINCT discord_id) DESC;",
         )
         .map_err(|_| "Database failure")?;
 
@@ -55,48 +58,68 @@ fn get_country_counts_buf() -> Result<String, String> {
 
     let mut buf = String::new();
 
-    for row in rows.take(10) {
-        if let Ok(row) = row {
-            buf += format!(
-                "**{}**: {}\n",
-                row.1.unwrap_or("Undefined Country".to_string()),
-                row.0.unwrap_or(0)
-            )
-            .as_str();
-        }
+    for row in rows.take(10).flatten() {
+        buf += format!(
+            "**{}**: {}\n",
+            row.1.unwrap_or("Undefined Country".to_string()),
+            row.0.unwrap_or(0)
+        )
+        .as_str();
     }
 
     Ok(buf)
 }
 
-async fn list_by_country(ctx: &Context, channel_id: &ChannelId) -> Result<(), String> {
+fn list_by_country() -> Result<CreateEmbed, String> {
     let countries = get_country_counts_buf()?;
 
-    let embed = CreateEmbed::new()
+    Ok(CreateEmbed::new()
         .title("Members by country:")
-        .description(countries);
-
-    channel_id
-        .send_message(&ctx.http, CreateMessage::new().embed(embed))
-        .await
-        .expect("Message failed to send");
-
-    Ok(())
+        .description(countries))
 }
 
-async fn list_by_game(ctx: &Context, channel_id: &ChannelId) -> Result<(), String> {
+fn list_by_game() -> Result<CreateEmbed, String> {
     let games = get_game_counts_buf()?;
 
-    let embed = CreateEmbed::new()
+    Ok(CreateEmbed::new()
         .title("Members by game:")
-        .description(games);
+        .description(games))
+}
 
-    channel_id
-        .send_message(&ctx.http, CreateMessage::new().embed(embed))
-        .await
-        .expect("Message failed to send");
+fn list(by: &str) -> Result<CreateEmbed, String> {
+    match by {
+        "country" => list_by_country(),
+        "game" => list_by_game(),
+        _ => Err("Invalid argument".to_string()),
+    }
+}
 
-    Ok(())
+pub fn register() -> CreateCommand {
+    let by = CreateCommandOption::new(3.into(), "by", "How to group the members")
+        .add_string_choice("country", "country")
+        .add_string_choice("game", "game")
+        .required(true);
+
+    CreateCommand::new("list")
+        .description("Count the verified members by country or game")
+        .add_option(by)
+}
+
+/// Handles the `/list` slash command.
+pub fn run(cmd_data: &CommandData) -> CreateInteractionResponseMessage {
+    let by = cmd_data
+        .options()
+        .iter()
+        .find_map(|option| match option.value {
+            ResolvedValue::String(by) if option.name == "by" => Some(by),
+            _ => None,
+        })
+        .unwrap_or_default();
+
+    match list(by) {
+        Ok(embed) => CreateInteractionResponseMessage::new().embed(embed),
+        Err(e) => CreateInteractionResponseMessage::new().content(e),
+    }
 }
 
 pub async fn execute(
@@ -105,11 +128,12 @@ pub async fn execute(
     _member: &Member,
     args: Args,
 ) -> Result<(), String> {
-    match args.arg(0).ok_or("Expected an argument".to_string())? {
-        "country" => list_by_country(ctx, channel_id).await?,
-        "game" => list_by_game(ctx, channel_id).await?,
-        _ => return Err("Invalid argument".to_string()),
-    }
+    let embed = list(args.arg(0).ok_or("Expected an argument".to_string())?)?;
+
+    channel_id
+        .send_message(&ctx.http, CreateMessage::new().embed(embed))
+        .await
+        .expect("Message failed to send");
 
     Ok(())
 }
@@ -1,4 +1,7 @@
+pub mod audit_command;
 pub mod config_command;
+pub mod leaderboard_command;
 pub mod list_command;
-pub mod verify_command;
+pub mod profile_command;
 pub mod remove_user_command;
+pub mod verify_command;
@@ -0,0 +1,118 @@
+use serenity::builder::{
+    CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseFollowup,
+    EditInteractionResponse,
+};
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use std::str::FromStr;
+
+use crate::game_api::GameProviders;
+use crate::user::{self, Game, LinkedAccount};
+
+// discord's limit of embeds per message
+const MAX_EMBEDS: usize = 10;
+
+pub fn register() -> CreateCommand {
+    let member = CreateCommandOption::new(
+        6.into(),
+        "member",
+        "The member to look up, defaults to yourself",
+    );
+
+    CreateCommand::new("profile")
+        .description("Show the game accounts linked to a member")
+        .add_option(member)
+}
+
+async fn account_embed(providers: &GameProviders, account: &LinkedAccount) -> CreateEmbed {
+    let provider = Game::from_str(&account.game)
+        .ok()
+        .and_then(|game| providers.get(game));
+
+    if let Some(provider) = provider
+        && let Some(user) = provider
+            .get_user(&provider.stored_identifier(&account.stored()))
+            .await
+    {
+        return user.create_profile_embed(&account.country);
+    }
+
+    CreateEmbed::new()
+        .title(format!("{} profile for {}", account.game, account.username))
+        .description("Could not fetch live data for this account")
+        .color(0xff66f0)
+}
+
+/// The message content and one embed per linked account.
+async fn build_response(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<(String, Vec<CreateEmbed>), String> {
+    let target = command
+        .data
+        .options()
+        .iter()
+        .find_map(|option| match option.value {
+            ResolvedValue::User(user, _) => Some(user.id),
+            _ => None,
+        })
+        .unwrap_or(command.user.id);
+
+    let accounts =
+        user::linked_accounts(Some(target.get())).map_err(|_| "Database failure".to_string())?;
+    if accounts.is_empty() {
+        return Ok((
+            format!("<@{target}> has not linked any game accounts"),
+            Vec::new(),
+        ));
+    }
+
+    let providers = ctx
+        .data
+        .read()
+        .await
+        .get::<GameProviders>()
+        .ok_or("Game providers are not loaded")?
+        .clone();
+
+    let mut embeds = Vec::new();
+    for account in accounts.iter() {
+        embeds.push(account_embed(&providers, account).await);
+    }
+
+    Ok((format!("Linked accounts of <@{target}>"), embeds))
+}
+
+/// Responds to the command itself, deferring first since every account is fetched live.
+pub async fn execute(ctx: &Context, command: &CommandInteraction) -> Result<(), String> {
+    command
+        .defer(&ctx.http)
+        .await
+        .map_err(|e| format!("Could not defer interaction: {e}"))?;
+
+    let (content, embeds) = build_response(ctx, command)
+        .await
+        .unwrap_or_else(|e| (e, Vec::new()));
+
+    // a message can only hold so many embeds, the rest go into follow up messages
+    let mut chunks = embeds.chunks(MAX_EMBEDS);
+
+    let response = EditInteractionResponse::new()
+        .content(content)
+        .embeds(chunks.next().unwrap_or_default().to_vec());
+    command
+        .edit_response(&ctx.http, response)
+        .await
+        .map_err(|e| format!("Could not edit interaction response: {e}"))?;
+
+    for chunk in chunks {
+        let followup = CreateInteractionResponseFollowup::new().embeds(chunk.to_vec());
+        command
+            .create_followup(&ctx.http, followup)
+            .await
+            .map_err(|e| format!("Could not send follow up message: {e}"))?;
+    }
+
+    Ok(())
+}
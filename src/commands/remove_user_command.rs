@@ -1,52 +1,310 @@
-use serenity::builder::{CreateCommand, CreateCommandOption};
+use serenity::builder::{
+    CreateAutocompleteResponse, CreateCommand, CreateCommandOption, EditMember,
+};
 use serenity::model::prelude::*;
 
-use rusqlite::{params, Connection};
+use serenity::prelude::*;
+
+use rusqlite::{Connection, params};
+
+use crate::audit::{AuditAction, AuditEntry};
+use crate::config::Config;
+use crate::game_api::TACHI_PLAYTYPES;
+use crate::ranks;
+use crate::user::{self, Game};
+
+use std::str::FromStr;
 
 pub fn register() -> CreateCommand {
-    let game = CreateCommandOption::new(3.into(), "game", "Set the game of the user registration").required(true);
+    let mut game =
+        CreateCommandOption::new(3.into(), "game", "Set the game of the user registration")
+            .required(true);
+    for choice in Game::ALL {
+        game = game.add_string_choice(choice.title(), choice.to_string());
+    }
+
+    let username = CreateCommandOption::new(
+        3.into(),
+        "username",
+        "Set the username of the user registration",
+    )
+    .set_autocomplete(true);
 
-    let username = CreateCommandOption::new(3.into(), "username", "Set the username of the user registration").required(true);
+    let member = CreateCommandOption::new(
+        6.into(),
+        "member",
+        "Remove the account of this member instead of looking up the username",
+    );
+
+    let reset_member = CreateCommandOption::new(
+        5.into(),
+        "reset_member",
+        "Also remove the country and member roles and reset the nickname",
+    );
+
+    let mut playtype = CreateCommandOption::new(
+        3.into(),
+        "playtype",
+        "Only remove the Tachi account of this playtype",
+    );
+    for choice in TACHI_PLAYTYPES {
+        playtype = playtype.add_string_choice(choice.name, choice.key);
+    }
 
     CreateCommand::new("remove_user")
         .description("Remove a user from the database")
         .default_member_permissions(Permissions::ADMINISTRATOR)
         .add_option(game)
         .add_option(username)
+        .add_option(member)
+        .add_option(reset_member)
+        .add_option(playtype)
+}
+
+/// Suggests usernames linked for the game that was already picked.
+pub fn autocomplete(cmd_data: &CommandData) -> CreateAutocompleteResponse {
+    let mut game = None;
+    let mut partial = "";
+
+    for option in cmd_data.options() {
+        match (option.name, option.value) {
+            ("game", ResolvedValue::String(value)) => game = Game::from_str(value).ok(),
+            ("username", ResolvedValue::Autocomplete { value, .. }) => partial = value,
+            _ => continue,
+        }
+    }
+
+    let Some(game) = game else {
+        return CreateAutocompleteResponse::new();
+    };
+
+    let usernames = Connection::open("users.db").and_then(|conn| {
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT username FROM users
+            WHERE game=?1 AND instr(lower(username), lower(?2)) > 0
+            ORDER BY username
+            LIMIT 25",
+        )?;
+
+        let rows = stmt.query_map(params![game.to_string(), partial], |row| {
+            row.get::<_, String>(0)
+        })?;
+
+        Ok(rows.flatten().collect::<Vec<String>>())
+    });
+
+    let mut response = CreateAutocompleteResponse::new();
+    for username in usernames.unwrap_or_default() {
+        response = response.add_string_choice(username.clone(), username);
+    }
+
+    response
+}
+
+struct RemovedAccount {
+    discord_id: u64,
+    username: String,
+    country: String,
+    variant: Option<String>,
+}
+
+/// Deletes the matching accounts of a game and returns them.
+fn remove_accounts(
+    game: Game,
+    username: Option<&str>,
+    member: Option<UserId>,
+    variant: Option<&str>,
+) -> Result<Vec<RemovedAccount>, RemoveUserError> {
+    const ACCOUNT_CLAUSE: &str = "game=?1 AND (?2 IS NULL OR username=?2)
+        AND (?3 IS NULL OR discord_id=?3) AND (?4 IS NULL OR variant=?4)";
+    let account = params![
+        game.to_string(),
+        username,
+        member.map(|member| member.get()),
+        variant
+    ];
+
+    let conn = Connection::open("users.db").map_err(|_| RemoveUserError::DatabaseFailure)?;
+
+    let removed = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT discord_id, username, country, variant FROM users WHERE {ACCOUNT_CLAUSE}"
+            ))
+            .map_err(|_| RemoveUserError::DatabaseFailure)?;
+
+        stmt.query_map(account, |row| {
+            Ok(RemovedAccount {
+                discord_id: row.get(0)?,
+                username: row.get(1)?,
+                country: row.get(2)?,
+                variant: row.get(3)?,
+            })
+        })
+        .map_err(|_| RemoveUserError::DatabaseFailure)?
+        .flatten()
+        .collect::<Vec<RemovedAccount>>()
+    };
+
+    conn.execute(
+        &format!("DELETE FROM users WHERE {ACCOUNT_CLAUSE}"),
+        account,
+    )
+    .map_err(|_| RemoveUserError::DatabaseFailure)?;
+
+    Ok(removed)
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum RemoveUserError {
     InvalidGame(String),
     DatabaseFailure,
-    InvalidOption
+    InvalidOption,
 }
 
-pub async fn execute(cmd_data: &CommandData) -> Result<String, RemoveUserError> {
-    let game = cmd_data.options().iter().find(|option| option.name == "game")
-        .ok_or(RemoveUserError::InvalidOption)?
-        .value.clone();
+pub async fn execute(
+    ctx: &Context,
+    command: &CommandInteraction,
+) -> Result<String, RemoveUserError> {
+    let mut game = None;
+    let mut username = None;
+    let mut member = None;
+    let mut reset_member = false;
+    let mut playtype = None;
 
-    let username = cmd_data.options().iter().find(|option| option.name == "username")
-        .ok_or(RemoveUserError::InvalidOption)?
-        .value.clone();
+    for option in command.data.options() {
+        match (option.name, option.value) {
+            ("game", ResolvedValue::String(value)) => {
+                game = Some(
+                    Game::from_str(value)
+                        .map_err(|_| RemoveUserError::InvalidGame(value.to_string()))?,
+                )
+            }
+            ("username", ResolvedValue::String(value)) => username = Some(value),
+            ("member", ResolvedValue::User(user, _)) => member = Some(user.id),
+            ("reset_member", ResolvedValue::Boolean(value)) => reset_member = value,
+            ("playtype", ResolvedValue::String(value)) => playtype = Some(value),
+            _ => continue,
+        }
+    }
 
-    let game = if let ResolvedValue::String(game_str) = game {
-        game_str
-    } else {
+    let game = game.ok_or(RemoveUserError::InvalidOption)?;
+    if username.is_none() && member.is_none() {
         return Err(RemoveUserError::InvalidOption);
-    };
+    }
 
-    let username = if let ResolvedValue::String(username_str) = username {
-        username_str
-    } else {
-        return Err(RemoveUserError::InvalidOption);
-    };
+    let removed = remove_accounts(game, username, member, playtype)?;
+    if removed.is_empty() {
+        return Ok(format!("No {} account matched", game.title()));
+    }
 
-    let conn = Connection::open("users.db").map_err(|_| RemoveUserError::DatabaseFailure)?;
+    let mut response = String::new();
+
+    for account in removed {
+        let discord_id = UserId::new(account.discord_id);
+        let username = account.username;
+
+        AuditEntry::new(command.user.id, AuditAction::RemoveUser)
+            .target(discord_id)
+            .before(format!("{game}: {username}"))
+            .record(&ctx.http)
+            .await;
+
+        response += &format!("Successfully removed {username} from the database\n");
+
+        // the rank refresh only looks at linked accounts, so it would never take these away
+        if let Some(guild_id) = command.guild_id
+            && let Ok(member) = guild_id.member(&ctx.http, discord_id).await
+            && let Err(e) =
+                ranks::sync_rank_roles(&ctx.http, &member, game, account.variant.as_deref(), None)
+                    .await
+        {
+            response += &format!("Could not remove the rank roles of <@{discord_id}>: {e}\n");
+        }
+
+        if !reset_member {
+            continue;
+        }
+
+        // roles and nickname come from the first verification, so they stay while any
+        // other account is still linked
+        let still_linked = user::has_linked_account(discord_id.get())
+            .map_err(|_| RemoveUserError::DatabaseFailure)?;
 
-    conn.execute("DELETE FROM users WHERE game=?1 AND username=?2", params![game, username])
-        .map_err(|_| RemoveUserError::DatabaseFailure)?;
+        response += &match command.guild_id {
+            Some(_) if still_linked => {
+                format!("<@{discord_id}> still has linked accounts, kept their roles and nickname")
+            }
+            Some(guild_id) => reset(ctx, guild_id, discord_id, &account.country)
+                .await
+                .unwrap_or_else(|e| format!("Could not reset <@{discord_id}>: {e}")),
+            None => "Members can only be reset from within the server".to_string(),
+        };
+        response += "\n";
+    }
 
-    Ok(format!("Successfully removed {username} from the database"))
+    Ok(response.trim_end().to_string())
+}
+
+/// Takes away what [`crate::verification::VerificationInfo::apply`] gave a member on their
+/// first verification and describes what was changed.
+async fn reset(
+    ctx: &Context,
+    guild_id: GuildId,
+    discord_id: UserId,
+    country: &str,
+) -> Result<String, String> {
+    let member = guild_id
+        .member(&ctx.http, discord_id)
+        .await
+        .map_err(|_| "they are no longer in the server".to_string())?;
+
+    let roles = guild_id
+        .roles(&ctx.http)
+        .await
+        .map_err(|e| format!("could not get the server's roles: {e}"))?;
+
+    let config = Config::load().unwrap_or_default();
+    let member_roles = config.member_roles(&roles).unwrap_or_default();
+
+    let removed_roles = member
+        .roles
+        .iter()
+        .filter_map(|role_id| roles.get(role_id))
+        .filter(|role| {
+            member_roles.contains(&role.id) || crate::remove_emojis(&role.name).trim() == country
+        })
+        .collect::<Vec<&Role>>();
+
+    let mut changes = Vec::new();
+
+    if !removed_roles.is_empty() {
+        let role_ids = removed_roles.iter().map(|role| role.id).collect::<Vec<_>>();
+        member
+            .remove_roles(&ctx.http, &role_ids)
+            .await
+            .map_err(|e| format!("could not remove roles: {e}"))?;
+
+        let names = removed_roles
+            .iter()
+            .map(|role| role.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        changes.push(format!("removed the roles {names}"));
+    }
+
+    if member.nick.is_some() && !config.keep_nicknames {
+        guild_id
+            .edit_member(&ctx.http, discord_id, EditMember::new().nickname(""))
+            .await
+            .map_err(|e| format!("could not reset the nickname: {e}"))?;
+
+        changes.push("reset the nickname".to_string());
+    }
+
+    Ok(if changes.is_empty() {
+        format!("<@{discord_id}> had no roles or nickname to reset")
+    } else {
+        format!("For <@{discord_id}>: {}", changes.join(" and "))
+    })
 }
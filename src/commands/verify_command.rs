@@ -1,97 +1,31 @@
 use serenity::builder::{
-    CreateButton, CreateEmbed, CreateMessage, CreateSelectMenu, CreateSelectMenuKind,
+    CreateButton, CreateCommand, CreateCommandOption, CreateEmbed, CreateMessage, CreateSelectMenu,
+    CreateSelectMenuKind, EditInteractionResponse,
 };
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 
 use crate::config::Config;
-use crate::game_api::{DMJam, Osu, Quaver, Tachi};
-use crate::user::User;
-use crate::verification::{PendingVerifications, VerificationInfo};
-use crate::Args;
-
-use rusqlite::Connection;
-
-const NOT_CONFIGURED: &'static str =
-    "The bot is not yet configured, an admin needs to use the /config command";
-
-async fn get_user_data(ctx: &Context, account: &str) -> Option<User> {
-    if account.starts_with("https://osu.ppy.sh/users/") || account.starts_with("osu.ppy.sh/users/")
-    {
-        // write access so that the access token can be refreshed upon timeout
-        let mut data = ctx.data.write().await;
-        let osu = data.get_mut::<Osu>().unwrap();
-
-        let mut parts = account.split("/");
-        while let Some(part) = parts.next() {
-            if part == "users" {
-                let user_id = parts.next()?;
-                let response = osu.get_user(user_id).await?;
-                let response_text = response.text().await.ok()?;
-
-                return User::from_osu(&response_text);
-            }
-        }
-        return None;
-    }
+use crate::game_api::{GameProviders, TACHI_PLAYTYPES};
+use crate::user::{Game, User};
+use crate::verification::{
+    self, OwnershipChallenge, OwnershipChallenges, PendingVerifications, VerificationInfo,
+};
 
-    if account.starts_with("https://quavergame.com/user")
-        || account.starts_with("quavergame.com/user")
-    {
-        let mut parts = account.split("/");
-        while let Some(part) = parts.next() {
-            if part == "user" {
-                let user_id = parts.next()?;
-                let response = Quaver::get_user(user_id).await?;
-                let response_text = response.text().await.ok()?;
-
-                return User::from_quaver(&response_text);
-            }
-        }
-    }
+use rusqlite::{Connection, OptionalExtension};
 
-    if account.starts_with("https://boku.tachi.ac/u/")
-        || account.starts_with("boku.tachi.ac/u/")
-        || account.starts_with("https://bokutachi.xyz/u/")
-        || account.starts_with("bokutachi.xyz/u/")
-    {
-        let mut parts = account.split("/");
-        while let Some(part) = parts.next() {
-            if part == "u" {
-                let user_id = parts.next()?;
+use std::str::FromStr;
 
-                let user_response = Tachi::get_user(user_id).await?;
-                let user_response_text = user_response.text().await.ok()?;
+const NOT_CONFIGURED: &str =
+    "The bot is not yet configured, an admin needs to use the /config command";
 
-                let game_stats_response = Tachi::get_game_stats(user_id, "bms", "7K").await?;
-                let game_stats_response_text = game_stats_response.text().await.ok()?;
+async fn get_user_data(ctx: &Context, game: Option<Game>, account: &str) -> Option<User> {
+    let providers = ctx.data.read().await.get::<GameProviders>()?.clone();
 
-                return User::from_tachi(&user_response_text, &game_stats_response_text);
-            }
-        }
-    }
-    if account.starts_with("https://dmjam.net/player-scoreboard/")
-        || account.starts_with("dmjam.net/player-scoreboard/")
-    {
-        let mut parts = account.split("/");
-        while let Some(part) = parts.next() {
-            if part == "player-scoreboard" {
-                let user_id = parts.next()?;
-                let response = DMJam::get_user(user_id).await?;
-                let response_text = response.text().await.ok()?;
-
-                return User::from_dmjam(&response_text);
-            }
-        }
+    match game {
+        Some(game) => providers.get_user_for_game(game, account).await,
+        None => providers.get_user(account).await,
     }
-
-    let mut data = ctx.data.write().await;
-    let osu = data.get_mut::<Osu>()?;
-
-    let response = osu.get_user(format!("@{}", account).as_str()).await?;
-    let response_text = response.text().await.ok()?;
-
-    return User::from_osu(&response_text);
 }
 
 async fn country_interaction(
@@ -168,6 +102,45 @@ pub async fn verify_user(
     Ok(())
 }
 
+/// Checks that the member has placed their challenge token on the profile, issuing a new
+/// challenge if they have none for this account yet.
+fn check_ownership(
+    challenges: &mut OwnershipChallenges,
+    discord_id: u64,
+    user: &User,
+) -> Result<(), VerificationError> {
+    if user.profile_text.is_none() {
+        // games without an editable profile can only be checked by the admins
+        return Ok(());
+    }
+
+    match challenges.get(&discord_id) {
+        Some(challenge) if challenge.is_solved_by(user) => {
+            challenges.remove(&discord_id);
+            Ok(())
+        }
+        Some(challenge) if challenge.is_for(user) => {
+            Err(VerificationError::VerificationFailed(format!(
+                "Could not find `{}` on your {} profile, add it and run the command again.",
+                challenge.token,
+                user.game.title()
+            )))
+        }
+        _ => {
+            let challenge = OwnershipChallenge::new(discord_id, user);
+            let message = format!(
+                "To prove that you own {}, add `{}` to your {} profile and run the command again. \
+                You can remove it once you are verified.",
+                user.username,
+                challenge.token,
+                user.game.title()
+            );
+            challenges.insert(discord_id, challenge);
+            Err(VerificationError::ChallengeIssued(message))
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum VerificationError {
     DatabaseError,
@@ -176,19 +149,126 @@ pub enum VerificationError {
     CouldNotLoadConfig,
     NotConfigured(String),
     VerificationFailed(String),
+    ChallengeIssued(String),
+    WrongChannel(ChannelId),
+    ProfileNotFound,
+}
+
+impl VerificationError {
+    /// The message shown to the member, if any.
+    pub fn message(&self) -> Option<String> {
+        match self {
+            Self::UserAlreadyExists(e)
+            | Self::NotConfigured(e)
+            | Self::VerificationFailed(e)
+            | Self::ChallengeIssued(e) => Some(e.clone()),
+            Self::NoArgumentSupplied => {
+                Some("Please provide a profile link to verify.".to_string())
+            }
+            Self::ProfileNotFound => Some("Could not find that profile.".to_string()),
+            Self::WrongChannel(channel) => Some(format!("Please verify in <#{channel}>.")),
+            Self::DatabaseError | Self::CouldNotLoadConfig => None,
+        }
+    }
+}
+
+pub fn register() -> CreateCommand {
+    let mut game = CreateCommandOption::new(
+        3.into(),
+        "game",
+        "The game of the account, detected from the link if left out",
+    );
+    for choice in Game::ALL {
+        game = game.add_string_choice(choice.title(), choice.to_string());
+    }
+
+    let profile = CreateCommandOption::new(
+        3.into(),
+        "profile",
+        "Link to your profile, or your username",
+    )
+    .required(true);
+
+    let mut playtype = CreateCommandOption::new(
+        3.into(),
+        "playtype",
+        "The Tachi game and playtype to verify for",
+    );
+    for choice in TACHI_PLAYTYPES {
+        playtype = playtype.add_string_choice(choice.name, choice.key);
+    }
+
+    CreateCommand::new("verify")
+        .description("Link a game account to your discord account")
+        .add_option(profile)
+        .add_option(game)
+        .add_option(playtype)
+}
+
+/// Handles the `/verify` slash command, replying privately with the outcome.
+pub async fn run(ctx: &Context, command: &CommandInteraction) -> Result<(), String> {
+    command
+        .defer_ephemeral(&ctx.http)
+        .await
+        .map_err(|e| format!("Could not defer interaction: {e}"))?;
+
+    let mut game = None;
+    let mut profile = None;
+    let mut playtype = None;
+    for option in command.data.options() {
+        match (option.name, option.value) {
+            ("game", ResolvedValue::String(value)) => game = Game::from_str(value).ok(),
+            ("profile", ResolvedValue::String(value)) => profile = Some(value.trim().to_string()),
+            ("playtype", ResolvedValue::String(value)) => playtype = Some(value),
+            _ => continue,
+        }
+    }
+
+    // a playtype is spelled the way tachi profile links do, which the tachi provider picks up
+    if let Some(playtype) = playtype {
+        game = Some(Game::BMS);
+        profile = profile.map(|profile| {
+            let profile = profile.trim_end_matches('/');
+            match profile.split_once("/games/") {
+                Some((user, _)) => format!("{user}/games/{playtype}"),
+                None => format!("{profile}/games/{playtype}"),
+            }
+        });
+    }
+
+    let result = match (command.member.as_deref(), profile) {
+        (Some(member), Some(profile)) => {
+            execute(ctx, &command.channel_id, member.clone(), game, &profile).await
+        }
+        (None, _) => Err(VerificationError::VerificationFailed(
+            "This command can only be used in the server".to_string(),
+        )),
+        (_, None) => Err(VerificationError::NoArgumentSupplied),
+    };
+
+    let content = match result {
+        Ok(()) => "Your verification request has been received.".to_string(),
+        Err(e) => e
+            .message()
+            .unwrap_or("Something went wrong, please contact an admin.".to_string()),
+    };
+
+    command
+        .edit_response(&ctx.http, EditInteractionResponse::new().content(content))
+        .await
+        .map_err(|e| format!("Could not edit interaction response: {e}"))?;
+
+    Ok(())
 }
 
 pub async fn execute(
     ctx: &Context,
     channel_id: &ChannelId,
     member: Member,
-    args: Args,
+    game: Option<Game>,
+    account: &str,
 ) -> Result<(), VerificationError> {
-    let user = get_user_data(
-        &ctx,
-        &args.arg(0).ok_or(VerificationError::NoArgumentSupplied)?,
-    )
-    .await;
+    let user = get_user_data(ctx, game, account).await;
 
     let mut data = ctx.data.write().await;
 
@@ -196,7 +276,7 @@ pub async fn execute(
 
     if let Some(verification_channel) = config.channels.verification_channel {
         if *channel_id != verification_channel {
-            return Ok(());
+            return Err(VerificationError::WrongChannel(verification_channel));
         }
     } else {
         return Err(VerificationError::NotConfigured(NOT_CONFIGURED.to_string()));
@@ -207,25 +287,30 @@ pub async fn execute(
         .admin_channel
         .ok_or(VerificationError::NotConfigured(NOT_CONFIGURED.to_string()))?;
 
-    let verifications = data
-        .get_mut::<PendingVerifications>()
-        .expect("No verification hashmap found");
-
-    if let Some(user) = user {
-        let id = verifications.use_current_id();
-
+    if let Some(mut user) = user {
         {
             let conn =
                 Connection::open("users.db").map_err(|_| VerificationError::DatabaseError)?;
 
             let discord_id = member.user.id.get();
 
-            let mut stmt = conn
-                .prepare("SELECT discord_id FROM users WHERE game=?1 AND username=?2")
-                .map_err(|_| VerificationError::DatabaseError)?;
-
-            if let Ok(other_discord_id) = 
-                stmt.query_one([user.game.to_string(), user.username.clone()], |row| row.get::<_, String>(0)) 
+            // the same username can belong to different players on different instances, and
+            // members may link their own account again for another tachi playtype
+            if let Some(other_discord_id) = conn
+                .query_row(
+                    "SELECT discord_id FROM users
+                    WHERE game=?1 AND username=?2 AND instance IS ?3 AND discord_id!=?4 AND active=1
+                    LIMIT 1",
+                    rusqlite::params![
+                        user.game.to_string(),
+                        user.username,
+                        user.instance,
+                        discord_id
+                    ],
+                    |row| row.get::<_, u64>(0),
+                )
+                .optional()
+                .map_err(|_| VerificationError::DatabaseError)?
             {
                 return Err(VerificationError::UserAlreadyExists(format!(
                     "That user is already verified by <@{other_discord_id}>,
@@ -233,40 +318,74 @@ pub async fn execute(
                 )));
             }
 
-            stmt = conn
-                .prepare("SELECT username FROM users WHERE discord_id=?1")
-                .map_err(|_| VerificationError::DatabaseError)?;
-
-
-            if let Ok(username) =
-                stmt.query_one([discord_id], |row| row.get::<_, String>(0))
+            // one account per game, or per playtype for tachi
+            if let Some(username) = conn
+                .query_row(
+                    "SELECT username FROM users
+                    WHERE discord_id=?1 AND game=?2 AND variant IS ?3 AND active=1
+                    LIMIT 1",
+                    rusqlite::params![discord_id, user.game.to_string(), user.variant],
+                    |row| row.get::<_, String>(0),
+                )
+                .optional()
+                .map_err(|_| VerificationError::DatabaseError)?
             {
                 return Err(VerificationError::UserAlreadyExists(format!(
-                    "User <@{discord_id}> is already verified with username: {username},
-                    please contact an admin"
+                    "User <@{discord_id}> is already verified for {} with username: {username},
+                    please contact an admin",
+                    user.game.variant_title(user.variant.as_deref())
                 )));
             }
 
+            // further accounts share the country picked on the first verification
+            if let Ok(country) = conn.query_row(
+                "SELECT country FROM users WHERE discord_id=?1 AND active=1 LIMIT 1",
+                [discord_id],
+                |row| row.get::<_, String>(0),
+            ) && let Some(code) = crate::code_from_country(&country)
+            {
+                user.country = Some(code.to_string());
+            }
+        }
+
+        if config.ownership_challenge {
+            let challenges = data
+                .get_mut::<OwnershipChallenges>()
+                .expect("No ownership challenges found");
+            check_ownership(challenges, member.user.id.get(), &user)?;
         }
 
+        let verifications = data
+            .get_mut::<PendingVerifications>()
+            .expect("No verification hashmap found");
+
+        let id = verifications.use_current_id();
+
         let mut verification_info = VerificationInfo {
             id: id as u32,
             discord_user: member.clone(),
             user,
             status_message: None,
             verification_message: None,
+            created_at: verification::unix_now(),
         };
 
-        if let Some(_) = verification_info.user.country {
-            verify_user(&ctx, &mut verification_info, &channel_id, &admin_channel)
+        if verification_info.user.country.is_some() {
+            verify_user(ctx, &mut verification_info, channel_id, &admin_channel)
                 .await
-                .map_err(|e| VerificationError::VerificationFailed(e))?;
+                .map_err(VerificationError::VerificationFailed)?;
             verifications.insert(id, verification_info);
         } else {
-            country_interaction(&ctx, &verification_info, &channel_id).await;
+            country_interaction(ctx, &verification_info, channel_id).await;
             verifications.insert(id, verification_info);
         };
-    };
+
+        if let Err(e) = verifications.save(id) {
+            eprintln!("{e}");
+        }
+    } else {
+        return Err(VerificationError::ProfileNotFound);
+    }
 
     Ok(())
 }
@@ -6,11 +6,25 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use std::fs::{self, File};
 use std::io::Write;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::user::Game;
 
 #[derive(Default, Serialize, Deserialize)]
 pub struct Channels {
     pub admin_channel: Option<ChannelId>,
     pub verification_channel: Option<ChannelId>,
+    /// Where audit log entries are posted, if anywhere.
+    #[serde(default)]
+    pub log_channel: Option<ChannelId>,
+}
+
+/// A role for members whose global rank in a game is `max_rank` or better.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RankRole {
+    pub max_rank: u32,
+    pub role: RoleId,
 }
 
 #[derive(Default, Serialize, Deserialize)]
@@ -18,13 +32,153 @@ pub struct Config {
     pub channels: Channels,
     pub emoji_exceptions: HashMap<String, String>,
     pub non_country_roles: HashSet<String>,
+    /// Hours after which an unanswered verification request expires.
+    #[serde(default)]
+    pub verification_ttl_hours: Option<u64>,
+    /// Require members to place a token on their game profile before a request is sent.
+    #[serde(default)]
+    pub ownership_challenge: bool,
+    /// Hours between refreshes of every linked account's ranks.
+    #[serde(default)]
+    pub rank_refresh_hours: Option<u64>,
+    /// Keep answering the old `!verify` and `!list` messages, this needs the message content
+    /// intent which is only requested at startup.
+    #[serde(default)]
+    pub prefix_commands: bool,
+    /// Rank bracket roles per ranking, see [`Game::ranking`].
+    #[serde(default)]
+    pub rank_roles: HashMap<String, Vec<RankRole>>,
+    /// Nickname given to verified members, see [`Config::nickname`] for the placeholders.
+    #[serde(default)]
+    pub nickname_template: Option<String>,
+    /// Leave the nicknames of members alone when they are verified.
+    #[serde(default)]
+    pub keep_nicknames: bool,
+    /// Role every verified member gets, falls back to the role named "Member" when unset.
+    #[serde(default)]
+    pub member_role: Option<RoleId>,
+    /// Further roles given to members on their first verification.
+    #[serde(default)]
+    pub approval_roles: HashSet<RoleId>,
+    /// Tachi game and playtype accounts are verified for when the request doesn't name one,
+    /// as `game/playtype`.
+    #[serde(default)]
+    pub tachi_playtype: Option<String>,
 }
 
 impl TypeMapKey for Config {
     type Value = Config;
 }
 
+const DEFAULT_VERIFICATION_TTL_HOURS: u64 = 72;
+const DEFAULT_RANK_REFRESH_HOURS: u64 = 24;
+const DEFAULT_NICKNAME_TEMPLATE: &str = "{username}";
+// discord's limit on nickname length
+const NICKNAME_LIMIT: usize = 32;
+
 impl Config {
+    pub fn verification_ttl_secs(&self) -> u64 {
+        self.verification_ttl_hours
+            .unwrap_or(DEFAULT_VERIFICATION_TTL_HOURS)
+            * 3600
+    }
+
+    pub fn rank_refresh_interval(&self) -> Duration {
+        Duration::from_secs(
+            self.rank_refresh_hours
+                .unwrap_or(DEFAULT_RANK_REFRESH_HOURS)
+                * 3600,
+        )
+    }
+
+    /// The narrowest bracket role of the ranking, see [`Game::ranking`], a global rank falls
+    /// into.
+    pub fn rank_role(&self, ranking: &str, rank: Option<u32>) -> Option<RoleId> {
+        let rank = rank?;
+
+        self.rank_roles
+            .get(ranking)?
+            .iter()
+            .filter(|bracket| rank <= bracket.max_rank)
+            .min_by_key(|bracket| bracket.max_rank)
+            .map(|bracket| bracket.role)
+    }
+
+    /// The member role followed by the approval roles that still exist in the server.
+    pub fn member_roles(&self, roles: &HashMap<RoleId, Role>) -> Result<Vec<RoleId>, String> {
+        let member_role = match self.member_role {
+            Some(role) => role,
+            // configs from before the member role could be set
+            None => roles
+                .values()
+                .find(|role| role.name == "Member")
+                .map(|role| role.id)
+                .ok_or("Member role is not set, use /config set_member_role")?,
+        };
+
+        if !roles.contains_key(&member_role) {
+            return Err(
+                "Member role does not exist anymore, use /config set_member_role".to_string(),
+            );
+        }
+
+        Ok(std::iter::once(member_role)
+            .chain(
+                self.approval_roles
+                    .iter()
+                    .filter(|role| roles.contains_key(role))
+                    .copied(),
+            )
+            .collect())
+    }
+
+    /// The nickname for a verified member, `None` if nicknames are left alone. The template
+    /// can use `{username}`, `{country}`, `{country_code}`, `{game}` and `{rank}`.
+    pub fn nickname(
+        &self,
+        game: Game,
+        username: &str,
+        country: &str,
+        rank: Option<u32>,
+    ) -> Option<String> {
+        if self.keep_nicknames {
+            return None;
+        }
+
+        let template = self
+            .nickname_template
+            .as_deref()
+            .unwrap_or(DEFAULT_NICKNAME_TEMPLATE)
+            .replace(
+                "{country_code}",
+                celes::Country::from_str(country).map_or("-", |country| country.alpha2),
+            )
+            .replace("{country}", country)
+            .replace("{game}", game.title())
+            .replace(
+                "{rank}",
+                &rank.map_or("-".to_string(), |rank| rank.to_string()),
+            );
+
+        let nickname = template.replace("{username}", username);
+        let overflow = nickname.chars().count().saturating_sub(NICKNAME_LIMIT);
+        if overflow == 0 {
+            return Some(nickname);
+        }
+
+        // shorten the username first so the rest of the template stays readable
+        let keep = username.chars().count().saturating_sub(overflow).max(1);
+        let username = username.chars().take(keep).collect::<String>();
+
+        let nickname = template
+            .replace("{username}", &username)
+            .chars()
+            .take(NICKNAME_LIMIT)
+            .collect::<String>();
+
+        Some(nickname.trim_end().to_string())
+    }
+
     pub fn save(&self) -> Result<(), String> {
         let config = serde_json::to_string(self).unwrap();
         let mut file = File::create("config.json").unwrap();
@@ -40,3 +194,76 @@ impl Config {
         Some(config)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_template(template: &str) -> Config {
+        Config {
+            nickname_template: Some(template.to_string()),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn renders_template() {
+        let config = with_template("[{country_code}] {username} #{rank}");
+
+        assert_eq!(
+            config.nickname(Game::Osu, "peppy", "Australia", Some(12)),
+            Some("[AU] peppy #12".to_string())
+        );
+        assert_eq!(
+            config.nickname(Game::Osu, "peppy", "Australia", None),
+            Some("[AU] peppy #-".to_string())
+        );
+    }
+
+    #[test]
+    fn keeps_nicknames_when_configured() {
+        let config = Config {
+            keep_nicknames: true,
+            ..with_template("{username}")
+        };
+
+        assert_eq!(config.nickname(Game::Osu, "peppy", "Australia", None), None);
+    }
+
+    #[test]
+    fn shortens_username_before_the_rest_of_the_template() {
+        let config = with_template("{username} | {country}");
+        let username = "a".repeat(30);
+
+        let nickname = config
+            .nickname(Game::Osu, &username, "Germany", None)
+            .unwrap();
+
+        assert_eq!(nickname, format!("{} | Germany", "a".repeat(22)));
+        assert_eq!(nickname.chars().count(), NICKNAME_LIMIT);
+    }
+
+    #[test]
+    fn cuts_template_when_shortening_the_username_is_not_enough() {
+        // the overflow is longer than the whole username
+        let config = with_template("{username} {country} {country} {country}");
+
+        let nickname = config
+            .nickname(Game::Osu, "peppy", "United Kingdom", None)
+            .unwrap();
+
+        assert_eq!(nickname, "p United Kingdom United Kingdom");
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        let config = with_template("{username}");
+        let username = "é".repeat(40);
+
+        let nickname = config
+            .nickname(Game::Osu, &username, "France", None)
+            .unwrap();
+
+        assert_eq!(nickname, "é".repeat(NICKNAME_LIMIT));
+    }
+}
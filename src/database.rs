@@ -0,0 +1,205 @@
+use rusqlite::{Connection, Transaction};
+
+/// Schema migrations for users.db, applied in order. The index of a migration plus one is
+/// the `user_version` the database has once it has been applied, so migrations must never
+/// be edited or reordered once released, only appended.
+const MIGRATIONS: &[fn(&Transaction) -> rusqlite::Result<()>] = &[
+    initial_schema,
+    accounts_per_game,
+    rank_history,
+    account_ranks,
+    audit_log,
+    member_activity,
+    pending_usernames,
+    account_variants,
+    account_instances,
+    accounts_per_playtype,
+    tachi_instances,
+];
+
+/// Brings the database up to the latest schema version.
+pub fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        migration(&tx)?;
+        tx.pragma_update(None, "user_version", index + 1)?;
+        tx.commit()?;
+
+        println!("Applied database migration {}", index + 1);
+    }
+
+    Ok(())
+}
+
+// `if not exists` so that databases created before migrations existed are adopted as is
+fn initial_schema(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "create table if not exists users (
+             discord_id integer primary key,
+             game text not null,
+             player_id integer not null,
+             username text not null,
+             country text not null
+         );
+
+         create table if not exists pending_verifications (
+             id integer primary key,
+             discord_id integer not null,
+             game text not null,
+             player_id integer not null,
+             country text,
+             status_channel_id integer,
+             status_message_id integer,
+             verification_channel_id integer,
+             verification_message_id integer,
+             created_at integer not null
+         );",
+    )
+}
+
+// one linked account per game per member instead of one per member
+fn accounts_per_game(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "create table users_new (
+             discord_id integer not null,
+             game text not null,
+             player_id integer not null,
+             username text not null,
+             country text not null,
+             primary key (discord_id, game)
+         );
+
+         insert into users_new (discord_id, game, player_id, username, country)
+             select discord_id, game, player_id, username, country from users;
+
+         drop table users;
+         alter table users_new rename to users;",
+    )
+}
+
+fn rank_history(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "create table rank_history (
+             id integer primary key,
+             discord_id integer not null,
+             game text not null,
+             player_id integer not null,
+             global_rank integer,
+             country_rank integer,
+             playtime integer,
+             level integer,
+             recorded_at integer not null
+         );
+
+         create index rank_history_account on rank_history (discord_id, game, recorded_at);",
+    )
+}
+
+// latest ranks kept on the account itself so leaderboards don't have to search the history
+fn account_ranks(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "alter table users add column global_rank integer;
+         alter table users add column country_rank integer;
+
+         update users set
+             global_rank = (select global_rank from rank_history h
+                 where h.discord_id = users.discord_id and h.game = users.game
+                 order by recorded_at desc limit 1),
+             country_rank = (select country_rank from rank_history h
+                 where h.discord_id = users.discord_id and h.game = users.game
+                 order by recorded_at desc limit 1);
+
+         create index users_game_rank on users (game, global_rank);",
+    )
+}
+
+fn audit_log(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "create table audit_log (
+             id integer primary key,
+             actor_id integer not null,
+             target_id integer,
+             action text not null,
+             before text,
+             after text,
+             created_at integer not null
+         );
+
+         create index audit_log_created_at on audit_log (created_at);",
+    )
+}
+
+// accounts of members who left the server are kept but no longer count as verified
+fn member_activity(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("alter table users add column active integer not null default 1;")
+}
+
+// some apis can only look players up by username
+fn pending_usernames(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch("alter table pending_verifications add column username text;")
+}
+
+// the part of a game an account was verified for, like the tachi playtype
+fn account_variants(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "alter table users add column variant text;
+         alter table pending_verifications add column variant text;",
+    )
+}
+
+fn account_instances(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "alter table users add column instance text;
+         alter table pending_verifications add column instance text;",
+    )
+}
+
+// one linked account per tachi playtype instead of one for all of them, accounts from before
+// playtypes were stored were verified for bms 7k
+fn accounts_per_playtype(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "create table users_new (
+             discord_id integer not null,
+             game text not null,
+             player_id integer not null,
+             username text not null,
+             country text not null,
+             global_rank integer,
+             country_rank integer,
+             active integer not null default 1,
+             variant text,
+             instance text
+         );
+
+         insert into users_new (
+             discord_id, game, player_id, username, country,
+             global_rank, country_rank, active, variant, instance
+         )
+             select discord_id, game, player_id, username, country,
+                 global_rank, country_rank, active, variant, instance
+             from users;
+
+         drop table users;
+         alter table users_new rename to users;
+
+         update users set variant = 'bms/7K' where game = 'bms' and variant is null;
+         update pending_verifications set variant = 'bms/7K' where game = 'bms' and variant is null;
+
+         create unique index users_account on users (discord_id, game, ifnull(variant, ''));
+         create index users_game_rank on users (game, global_rank);
+
+         alter table rank_history add column variant text;
+         update rank_history set variant = 'bms/7K' where game = 'bms';",
+    )
+}
+
+// every tachi account was looked up on bokutachi before the instance was stored
+fn tachi_instances(tx: &Transaction) -> rusqlite::Result<()> {
+    tx.execute_batch(
+        "update users set instance = 'boku.tachi.ac' where game = 'bms' and instance is null;
+         update pending_verifications set instance = 'boku.tachi.ac'
+             where game = 'bms' and instance is null;",
+    )
+}
@@ -0,0 +1,83 @@
+use reqwest::{Client, Response};
+use serde::Deserialize;
+
+use super::{GameProvider, path_identifier};
+use crate::user::{Game, Ranks, User};
+
+pub struct DMJam {
+    client: Client,
+    api_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct DMJamUser {
+    player_code: u32,
+    nickname: String,
+    player_ranking: u32,
+    level: u32,
+}
+
+impl DMJam {
+    pub const API_URL: &str = "https://dmjam.net";
+
+    pub fn new(client: Client, api_url: &str) -> Self {
+        Self {
+            client,
+            api_url: api_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn fetch_user(&self, user_id: &str) -> Option<Response> {
+        let api_url = format!("{}/api/player/{}", self.api_url, user_id);
+
+        self.client.get(api_url).send().await.ok()
+    }
+
+    fn parse_user(response: &str) -> Option<User> {
+        let response = serde_json::from_str::<DMJamUser>(response).ok()?;
+
+        let ranks = Ranks {
+            global: Some(response.player_ranking),
+            country: None,
+        };
+
+        let link = format!(
+            "https://dmjam.net/player-scoreboard/{}/2",
+            response.player_code
+        );
+
+        Some(User {
+            game: Game::DMJam,
+            user_id: response.player_code,
+            username: response.nickname,
+            avatar_url: String::new(),
+            ranks,
+            country: None,
+            link,
+            playtime: None,
+            level: Some(response.level),
+            rating: None,
+            variant: None,
+            instance: None,
+            profile_text: None,
+        })
+    }
+}
+
+#[serenity::async_trait]
+impl GameProvider for DMJam {
+    fn game(&self) -> Game {
+        Game::DMJam
+    }
+
+    fn parse_identifier(&self, account: &str) -> Option<String> {
+        path_identifier(account, &["dmjam.net"], "player-scoreboard")
+    }
+
+    async fn get_user(&self, identifier: &str) -> Option<User> {
+        let response = self.fetch_user(identifier).await?;
+        let response_text = response.text().await.ok()?;
+
+        Self::parse_user(&response_text)
+    }
+}
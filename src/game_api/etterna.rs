@@ -0,0 +1,106 @@
+use reqwest::{Client, Response};
+use serde::Deserialize;
+
+use super::{GameProvider, StoredAccount, path_identifier};
+use crate::user::{Game, Ranks, User};
+
+pub struct Etterna {
+    client: Client,
+    api_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct EtternaUserResponse {
+    data: EtternaUser,
+}
+
+#[derive(Deserialize, Debug)]
+struct EtternaUser {
+    id: u32,
+    username: String,
+    avatar_url: Option<String>,
+    country: Option<EtternaCountry>,
+    rank: Option<u32>,
+    player_rating: Option<f32>,
+    bio: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EtternaCountry {
+    code: String,
+}
+
+impl Etterna {
+    pub const API_URL: &str = "https://api.etternaonline.com";
+
+    pub fn new(client: Client, api_url: &str) -> Self {
+        Self {
+            client,
+            api_url: api_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn fetch_user(&self, username: &str) -> Option<Response> {
+        let api_url = format!("{}/api/users/{}", self.api_url, username);
+
+        self.client.get(api_url).send().await.ok()
+    }
+
+    fn parse_user(response: &str) -> Option<User> {
+        let response = serde_json::from_str::<EtternaUserResponse>(response)
+            .ok()?
+            .data;
+
+        let ranks = Ranks {
+            global: response.rank,
+            country: None,
+        };
+
+        let link = format!("https://etternaonline.com/users/{}", response.username);
+
+        Some(User {
+            game: Game::Etterna,
+            user_id: response.id,
+            username: response.username,
+            country: response.country.map(|country| country.code),
+            avatar_url: response.avatar_url.unwrap_or_default(),
+            link,
+            ranks,
+            playtime: None,
+            level: None,
+            rating: response.player_rating,
+            variant: None,
+            instance: None,
+            profile_text: Some(response.bio.unwrap_or_default()),
+        })
+    }
+}
+
+#[serenity::async_trait]
+impl GameProvider for Etterna {
+    fn game(&self) -> Game {
+        Game::Etterna
+    }
+
+    fn parse_identifier(&self, account: &str) -> Option<String> {
+        // profile links moved from /user/ to /users/ with the site rewrite
+        path_identifier(account, &["etternaonline.com"], "users")
+            .or_else(|| path_identifier(account, &["etternaonline.com"], "user"))
+    }
+
+    fn identifier_from_username(&self, username: &str) -> Option<String> {
+        Some(username.to_string())
+    }
+
+    fn stored_identifier(&self, account: &StoredAccount) -> String {
+        // the api only looks players up by username
+        account.username.to_string()
+    }
+
+    async fn get_user(&self, identifier: &str) -> Option<User> {
+        let response = self.fetch_user(identifier).await?;
+        let response_text = response.text().await.ok()?;
+
+        Self::parse_user(&response_text)
+    }
+}
@@ -0,0 +1,110 @@
+use reqwest::{Client, Response};
+use serde::Deserialize;
+
+use super::{GameProvider, path_identifier};
+use crate::user::{Game, Ranks, User};
+
+// key mode is ranked per key count, only 7K is of interest here
+const KEY_MODE: u32 = 0;
+const KEY_COUNT: u32 = 7;
+
+pub struct Malody {
+    client: Client,
+    api_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct MalodyUserResponse {
+    data: MalodyUser,
+}
+
+#[derive(Deserialize, Debug)]
+struct MalodyUser {
+    uid: u32,
+    username: String,
+    avatar: Option<String>,
+    sign: Option<String>,
+    #[serde(default)]
+    modes: Vec<MalodyModeStats>,
+}
+
+#[derive(Deserialize, Debug)]
+struct MalodyModeStats {
+    mode: u32,
+    key: Option<u32>,
+    rank: Option<u32>,
+    level: Option<u32>,
+    /// Play time in seconds.
+    playtime: Option<u32>,
+}
+
+impl Malody {
+    pub const API_URL: &str = "https://m.mugzone.net";
+
+    pub fn new(client: Client, api_url: &str) -> Self {
+        Self {
+            client,
+            api_url: api_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn fetch_user(&self, user_id: &str) -> Option<Response> {
+        let api_url = format!("{}/api/user/{}", self.api_url, user_id);
+
+        self.client.get(api_url).send().await.ok()
+    }
+
+    fn parse_user(response: &str) -> Option<User> {
+        let response = serde_json::from_str::<MalodyUserResponse>(response)
+            .ok()?
+            .data;
+
+        let stats = response
+            .modes
+            .iter()
+            .find(|stats| stats.mode == KEY_MODE && stats.key == Some(KEY_COUNT));
+
+        let ranks = Ranks {
+            global: stats.and_then(|stats| stats.rank),
+            country: None,
+        };
+
+        let link = format!("https://m.mugzone.net/accounts/user/{}", response.uid);
+
+        Some(User {
+            game: Game::Malody,
+            user_id: response.uid,
+            username: response.username,
+            country: None,
+            avatar_url: response.avatar.unwrap_or_default(),
+            link,
+            ranks,
+            playtime: stats
+                .and_then(|stats| stats.playtime)
+                .map(|playtime| playtime / 3600),
+            level: stats.and_then(|stats| stats.level),
+            rating: None,
+            variant: None,
+            instance: None,
+            profile_text: Some(response.sign.unwrap_or_default()),
+        })
+    }
+}
+
+#[serenity::async_trait]
+impl GameProvider for Malody {
+    fn game(&self) -> Game {
+        Game::Malody
+    }
+
+    fn parse_identifier(&self, account: &str) -> Option<String> {
+        path_identifier(account, &["m.mugzone.net"], "accounts/user")
+    }
+
+    async fn get_user(&self, identifier: &str) -> Option<User> {
+        let response = self.fetch_user(identifier).await?;
+        let response_text = response.text().await.ok()?;
+
+        Self::parse_user(&response_text)
+    }
+}
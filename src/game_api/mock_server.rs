@@ -0,0 +1,37 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Starts an http server on a free local port that answers each path in `routes` with its
+/// json body and everything else with a 404. Returns the base url to give a client.
+pub async fn serve(routes: &[(&str, &str)]) -> String {
+    let routes = routes
+        .iter()
+        .map(|(path, body)| (path.to_string(), body.to_string()))
+        .collect::<Vec<(String, String)>>();
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            // the clients only send small requests, the request line is all that is needed
+            let mut request = vec![0; 8192];
+            let read = stream.read(&mut request).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&request[..read]);
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+            let response = match routes.iter().find(|(route, _)| route == path) {
+                Some((_, body)) => format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                    body.len()
+                ),
+                None => "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"
+                    .to_string(),
+            };
+
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+
+    url
+}
@@ -0,0 +1,327 @@
+mod dmjam;
+mod etterna;
+mod malody;
+mod osu;
+mod quaver;
+mod tachi;
+
+pub use dmjam::DMJam;
+pub use etterna::Etterna;
+pub use malody::Malody;
+pub use osu::Osu;
+pub use quaver::Quaver;
+pub use tachi::{TACHI_PLAYTYPES, Tachi, TachiPlaytype};
+
+use serenity::prelude::TypeMapKey;
+
+use std::sync::Arc;
+
+use crate::user::{Game, User};
+
+/// A game that users can verify with.
+///
+/// Each provider knows how to recognise its own profile links, how to fetch a player
+/// from its api and how to turn that response into a [`User`].
+#[serenity::async_trait]
+pub trait GameProvider: Send + Sync {
+    fn game(&self) -> Game;
+
+    /// Extracts the player identifier from a profile link, or `None` if the link does not
+    /// belong to this game.
+    fn parse_identifier(&self, account: &str) -> Option<String>;
+
+    /// Turns a bare username into an identifier, used when no profile link matched.
+    fn identifier_from_username(&self, _username: &str) -> Option<String> {
+        None
+    }
+
+    /// The identifier to fetch an already linked account with, its player id unless the api
+    /// needs something else.
+    fn stored_identifier(&self, account: &StoredAccount) -> String {
+        account.player_id.to_string()
+    }
+
+    /// Fetches a player by the identifier returned from [`GameProvider::parse_identifier`]
+    /// or by their stored player id.
+    async fn get_user(&self, identifier: &str) -> Option<User>;
+}
+
+/// What is stored about a linked account, enough to fetch it again.
+pub struct StoredAccount<'a> {
+    pub player_id: u32,
+    pub username: &'a str,
+    /// See [`User::variant`].
+    pub variant: Option<&'a str>,
+    /// See [`User::instance`].
+    pub instance: Option<&'a str>,
+}
+
+#[derive(Default)]
+pub struct GameProviders {
+    providers: Vec<Box<dyn GameProvider>>,
+}
+
+impl GameProviders {
+    pub fn register(&mut self, provider: impl GameProvider + 'static) {
+        self.providers.push(Box::new(provider));
+    }
+
+    pub fn get(&self, game: Game) -> Option<&dyn GameProvider> {
+        self.providers
+            .iter()
+            .find(|provider| provider.game() == game)
+            .map(|provider| provider.as_ref())
+    }
+
+    /// Resolves a profile link, falling back to treating the input as a username.
+    pub async fn get_user(&self, account: &str) -> Option<User> {
+        for provider in self.providers.iter() {
+            if let Some(identifier) = provider.parse_identifier(account) {
+                return provider.get_user(&identifier).await;
+            }
+        }
+
+        for provider in self.providers.iter() {
+            if let Some(identifier) = provider.identifier_from_username(account) {
+                return provider.get_user(&identifier).await;
+            }
+        }
+
+        None
+    }
+
+    /// Resolves a profile link or username for a specific game.
+    pub async fn get_user_for_game(&self, game: Game, account: &str) -> Option<User> {
+        let provider = self.get(game)?;
+
+        let identifier = provider
+            .parse_identifier(account)
+            .or_else(|| provider.identifier_from_username(account))?;
+
+        provider.get_user(&identifier).await
+    }
+}
+
+impl TypeMapKey for GameProviders {
+    type Value = Arc<GameProviders>;
+}
+
+/// Returns the path segment following `path` when `account` is a link on one of `hosts`.
+/// `path` may span several segments, like `accounts/user`.
+pub(crate) fn path_identifier(account: &str, hosts: &[&str], path: &str) -> Option<String> {
+    let account = account
+        .strip_prefix("https://")
+        .or_else(|| account.strip_prefix("http://"))
+        .unwrap_or(account);
+
+    let mut parts = account.split('/');
+    if !hosts.contains(&parts.next()?) {
+        return None;
+    }
+
+    for segment in path.split('/') {
+        if parts.next()? != segment {
+            return None;
+        }
+    }
+
+    parts
+        .next()
+        .filter(|identifier| !identifier.is_empty())
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod mock_server;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_identifier_accepts_links_with_or_without_scheme() {
+        for account in [
+            "https://osu.ppy.sh/users/2",
+            "http://osu.ppy.sh/users/2",
+            "osu.ppy.sh/users/2",
+            "https://osu.ppy.sh/users/2/mania",
+        ] {
+            assert_eq!(
+                path_identifier(account, &["osu.ppy.sh"], "users"),
+                Some("2".to_string()),
+                "{account}"
+            );
+        }
+    }
+
+    #[test]
+    fn path_identifier_rejects_other_links() {
+        let hosts = &["osu.ppy.sh"];
+
+        assert_eq!(
+            path_identifier("https://example.com/users/2", hosts, "users"),
+            None
+        );
+        assert_eq!(
+            path_identifier("https://osu.ppy.sh/beatmaps/2", hosts, "users"),
+            None
+        );
+        assert_eq!(
+            path_identifier("https://osu.ppy.sh/users/", hosts, "users"),
+            None
+        );
+        assert_eq!(
+            path_identifier("https://osu.ppy.sh/users", hosts, "users"),
+            None
+        );
+        assert_eq!(path_identifier("someone", hosts, "users"), None);
+    }
+
+    #[test]
+    fn path_identifier_matches_multi_segment_paths() {
+        let hosts = &["m.mugzone.net"];
+
+        assert_eq!(
+            path_identifier(
+                "https://m.mugzone.net/accounts/user/42",
+                hosts,
+                "accounts/user"
+            ),
+            Some("42".to_string())
+        );
+        assert_eq!(
+            path_identifier("https://m.mugzone.net/accounts/42", hosts, "accounts/user"),
+            None
+        );
+    }
+
+    /// Resolves a profile link through the registry against a mock api, the same way
+    /// `/verify` does.
+    #[tokio::test]
+    async fn verifies_against_a_mock_api() {
+        let api_url = mock_server::serve(&[(
+            "/v2/user/42",
+            r#"{"user": {"id": 42, "username": "someone", "avatar_url": "", "country": "DE",
+                "about": "7KG-0000", "stats_keys7": {"ranks": {"global": 100, "country": 5}}}}"#,
+        )])
+        .await;
+
+        let mut providers = GameProviders::default();
+        providers.register(Quaver::new(reqwest::Client::new(), &api_url));
+
+        let user = providers
+            .get_user_for_game(Game::Quaver, "https://quavergame.com/user/42")
+            .await
+            .unwrap();
+
+        assert_eq!(user.user_id, 42);
+        assert_eq!(user.username, "someone");
+        assert_eq!(user.country.as_deref(), Some("DE"));
+        assert_eq!(user.ranks.global, Some(100));
+        assert_eq!(user.ranks.country, Some(5));
+        assert_eq!(user.profile_text.as_deref(), Some("7KG-0000"));
+
+        assert!(
+            providers
+                .get_user_for_game(Game::Quaver, "https://quavergame.com/user/43")
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn osu_authenticates_against_the_configured_api() {
+        let api_url = mock_server::serve(&[
+            (
+                "/oauth/token",
+                r#"{"access_token": "token", "expires_in": 86400}"#,
+            ),
+            (
+                "/api/v2/users/2/mania",
+                r#"{"id": 2, "username": "peppy", "avatar_url": "", "country": {"code": "AU"},
+                    "statistics": {"global_rank": 1, "country_rank": 1, "play_time": 7200}}"#,
+            ),
+        ])
+        .await;
+
+        let osu = Osu::build(reqwest::Client::new(), "id", "secret", &api_url)
+            .await
+            .unwrap();
+        let identifier = osu.parse_identifier("https://osu.ppy.sh/users/2").unwrap();
+        let user = osu.get_user(&identifier).await.unwrap();
+
+        assert_eq!(user.username, "peppy");
+        assert_eq!(user.ranks.global, Some(1));
+        assert_eq!(user.playtime, Some(2));
+    }
+
+    #[tokio::test]
+    async fn dmjam_fetches_from_the_configured_api() {
+        let api_url = mock_server::serve(&[(
+            "/api/player/7",
+            r#"{"player_code": 7, "nickname": "someone", "player_ranking": 12, "level": 50}"#,
+        )])
+        .await;
+
+        let dmjam = DMJam::new(reqwest::Client::new(), &api_url);
+        let identifier = dmjam
+            .parse_identifier("https://dmjam.net/player-scoreboard/7/2")
+            .unwrap();
+        let user = dmjam.get_user(&identifier).await.unwrap();
+
+        assert_eq!(user.user_id, 7);
+        assert_eq!(user.username, "someone");
+        assert_eq!(user.ranks.global, Some(12));
+        assert_eq!(user.level, Some(50));
+        assert_eq!(user.link, "https://dmjam.net/player-scoreboard/7/2");
+    }
+
+    #[tokio::test]
+    async fn etterna_fetches_from_the_configured_api() {
+        let api_url = mock_server::serve(&[(
+            "/api/users/someone",
+            r#"{"data": {"id": 3, "username": "someone", "avatar_url": null,
+                "country": {"code": "FR"}, "rank": 250, "player_rating": 28.5, "bio": "7KG-0000"}}"#,
+        )])
+        .await;
+
+        let etterna = Etterna::new(reqwest::Client::new(), &api_url);
+        let identifier = etterna
+            .parse_identifier("https://etternaonline.com/users/someone")
+            .unwrap();
+        let user = etterna.get_user(&identifier).await.unwrap();
+
+        assert_eq!(user.user_id, 3);
+        assert_eq!(user.country.as_deref(), Some("FR"));
+        assert_eq!(user.ranks.global, Some(250));
+        assert_eq!(user.rating, Some(28.5));
+        assert_eq!(user.profile_text.as_deref(), Some("7KG-0000"));
+        assert!(etterna.get_user("someone-else").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn malody_fetches_from_the_configured_api() {
+        let api_url = mock_server::serve(&[(
+            "/api/user/42",
+            r#"{"data": {"uid": 42, "username": "someone", "avatar": null, "sign": "",
+                "modes": [
+                    {"mode": 0, "key": 4, "rank": 1, "level": 90, "playtime": 36000},
+                    {"mode": 0, "key": 7, "rank": 80, "level": 30, "playtime": 7200}
+                ]}}"#,
+        )])
+        .await;
+
+        let malody = Malody::new(reqwest::Client::new(), &api_url);
+        let identifier = malody
+            .parse_identifier("https://m.mugzone.net/accounts/user/42")
+            .unwrap();
+        let user = malody.get_user(&identifier).await.unwrap();
+
+        // only the 7K stats count
+        assert_eq!(user.username, "someone");
+        assert_eq!(user.ranks.global, Some(80));
+        assert_eq!(user.level, Some(30));
+        assert_eq!(user.playtime, Some(2));
+        assert_eq!(user.link, "https://m.mugzone.net/accounts/user/42");
+    }
+}
@@ -0,0 +1,208 @@
+use reqwest::{Client, Method, Response, Url};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use super::{GameProvider, path_identifier};
+use crate::user::{Game, Ranks, User};
+
+pub struct Osu {
+    client: Client,
+    client_id: String,
+    client_secret: String,
+    api_url: String,
+    token: Mutex<OsuToken>,
+}
+
+struct OsuToken {
+    access_token: String,
+    expires_in: Duration,
+    refreshed_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct OsuAuth {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(Deserialize, Debug)]
+struct OsuUser {
+    pub username: String,
+    pub country: OsuCountry,
+    pub statistics: OsuUserStatistics,
+    pub avatar_url: String,
+    pub id: u32,
+    pub page: Option<OsuUserPage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OsuUserPage {
+    pub raw: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct OsuUserStatistics {
+    pub global_rank: Option<u32>,
+    pub country_rank: Option<u32>,
+    pub play_time: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OsuCountry {
+    pub code: String,
+}
+
+impl Osu {
+    pub const API_URL: &str = "https://osu.ppy.sh";
+
+    pub async fn build(
+        client: Client,
+        osu_client_id: &str,
+        osu_client_secret: &str,
+        api_url: &str,
+    ) -> Option<Self> {
+        let api_url = api_url.trim_end_matches('/');
+        let token = Self::request_token(&client, osu_client_id, osu_client_secret, api_url).await?;
+
+        Some(Self {
+            client_id: osu_client_id.to_string(),
+            client_secret: osu_client_secret.to_string(),
+            api_url: api_url.to_string(),
+            client,
+            token: Mutex::new(token),
+        })
+    }
+
+    async fn request_token(
+        client: &Client,
+        osu_client_id: &str,
+        osu_client_secret: &str,
+        api_url: &str,
+    ) -> Option<OsuToken> {
+        let url = Url::from_str(&format!("{}/oauth/token", api_url)).ok()?;
+
+        let request_builder = client.request(Method::POST, url);
+
+        let body = format!(
+            "client_id={}&client_secret={}&grant_type=client_credentials&scope=public",
+            osu_client_id, osu_client_secret
+        );
+
+        let request = request_builder
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", "application/json")
+            .body(body)
+            .build()
+            .ok()?;
+
+        let auth_raw = client.execute(request).await.ok()?;
+        let auth: OsuAuth = auth_raw.json().await.ok()?;
+
+        Some(OsuToken {
+            access_token: auth.access_token,
+            expires_in: Duration::from_secs(auth.expires_in),
+            refreshed_at: Instant::now(),
+        })
+    }
+
+    async fn refresh_token(&self, token: &mut OsuToken) {
+        if let Some(new_token) = Self::request_token(
+            &self.client,
+            &self.client_id,
+            &self.client_secret,
+            &self.api_url,
+        )
+        .await
+        {
+            *token = new_token;
+        }
+    }
+
+    async fn fetch_user(&self, user_id: &str) -> Option<Response> {
+        // held for the whole request so that only one caller refreshes an expired token
+        let mut token = self.token.lock().await;
+
+        for _ in 0..3 {
+            let api_url =
+                Url::from_str(&format!("{}/api/v2/users/{}/mania", self.api_url, user_id)).ok()?;
+
+            let request_builder = self.client.request(Method::GET, api_url);
+
+            let request = request_builder
+                .header("Content-Type", "application/json")
+                .header("Accept", "application/json")
+                .header(
+                    "Authorization",
+                    format!("Bearer {token}", token = token.access_token),
+                )
+                .build()
+                .unwrap();
+
+            match self.client.execute(request).await {
+                Ok(response) => return Some(response),
+                Err(_) => {
+                    if Instant::now().duration_since(token.refreshed_at) >= token.expires_in {
+                        self.refresh_token(&mut token).await;
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    fn parse_user(response: &str) -> Option<User> {
+        let response = serde_json::from_str::<OsuUser>(response).ok()?;
+        let link = format!("http://osu.ppy.sh/users/{}", response.id);
+
+        let ranks = Ranks {
+            global: response.statistics.global_rank,
+            country: response.statistics.country_rank,
+        };
+
+        let playtime = response
+            .statistics
+            .play_time
+            .map(|playtime| (playtime / 3600) as u32);
+
+        Some(User {
+            game: Game::Osu,
+            user_id: response.id,
+            username: response.username.to_string(),
+            avatar_url: response.avatar_url.to_string(),
+            country: Some(response.country.code.to_string()),
+            ranks,
+            link,
+            playtime,
+            level: None,
+            rating: None,
+            variant: None,
+            instance: None,
+            profile_text: Some(response.page.map(|page| page.raw).unwrap_or_default()),
+        })
+    }
+}
+
+#[serenity::async_trait]
+impl GameProvider for Osu {
+    fn game(&self) -> Game {
+        Game::Osu
+    }
+
+    fn parse_identifier(&self, account: &str) -> Option<String> {
+        path_identifier(account, &["osu.ppy.sh"], "users")
+    }
+
+    fn identifier_from_username(&self, username: &str) -> Option<String> {
+        Some(format!("@{}", username))
+    }
+
+    async fn get_user(&self, identifier: &str) -> Option<User> {
+        let response = self.fetch_user(identifier).await?;
+        let response_text = response.text().await.ok()?;
+
+        Self::parse_user(&response_text)
+    }
+}
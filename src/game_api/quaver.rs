@@ -0,0 +1,88 @@
+use reqwest::{Client, Response};
+use serde::Deserialize;
+
+use super::{GameProvider, path_identifier};
+use crate::user::{Game, Ranks, User};
+
+pub struct Quaver {
+    client: Client,
+    api_url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct QuaverUserResponse {
+    pub user: QuaverUser,
+}
+
+#[derive(Deserialize, Debug)]
+struct QuaverUser {
+    pub id: u32,
+    pub username: String,
+    pub avatar_url: String,
+    pub stats_keys7: QuaverUserStatistics,
+    pub country: String,
+    pub about: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct QuaverUserStatistics {
+    pub ranks: Ranks,
+}
+
+impl Quaver {
+    pub const API_URL: &str = "https://api.quavergame.com";
+
+    pub fn new(client: Client, api_url: &str) -> Self {
+        Self {
+            client,
+            api_url: api_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    async fn fetch_user(&self, user_id: &str) -> Option<Response> {
+        let api_url = format!("{}/v2/user/{}", self.api_url, user_id);
+
+        self.client.get(api_url).send().await.ok()
+    }
+
+    fn parse_user(response: &str) -> Option<User> {
+        let response = serde_json::from_str::<QuaverUserResponse>(response)
+            .ok()?
+            .user;
+        let link = format!("https://quavergame.com/user/{}", response.id);
+
+        Some(User {
+            game: Game::Quaver,
+            user_id: response.id,
+            username: response.username.to_string(),
+            country: Some(response.country.to_string()),
+            avatar_url: response.avatar_url.to_string(),
+            ranks: response.stats_keys7.ranks,
+            link,
+            playtime: None,
+            level: None,
+            rating: None,
+            variant: None,
+            instance: None,
+            profile_text: Some(response.about.unwrap_or_default()),
+        })
+    }
+}
+
+#[serenity::async_trait]
+impl GameProvider for Quaver {
+    fn game(&self) -> Game {
+        Game::Quaver
+    }
+
+    fn parse_identifier(&self, account: &str) -> Option<String> {
+        path_identifier(account, &["quavergame.com"], "user")
+    }
+
+    async fn get_user(&self, identifier: &str) -> Option<User> {
+        let response = self.fetch_user(identifier).await?;
+        let response_text = response.text().await.ok()?;
+
+        Self::parse_user(&response_text)
+    }
+}
@@ -0,0 +1,400 @@
+use reqwest::{Client, Response};
+use serde::Deserialize;
+
+use std::collections::HashMap;
+
+use super::{GameProvider, StoredAccount, path_identifier};
+use crate::config::Config;
+use crate::user::{Game, Ranks, User};
+
+pub struct Tachi {
+    client: Client,
+    /// Replaces the instance of every account when set, for pointing the client at one
+    /// server such as a mock.
+    api_url: Option<String>,
+}
+
+/// A game and playtype on Tachi, with the rating algorithm its rankings are based on.
+pub struct TachiPlaytype {
+    /// The `game/playtype` path used by the api and profile links, also what is stored.
+    pub key: &'static str,
+    pub name: &'static str,
+    algorithm: &'static str,
+    /// Host of the instance that tracks the playtype, for accounts that don't name one.
+    instance: &'static str,
+}
+
+/// A Tachi server, the first host is where its api is reached and the rest are old domains
+/// its profile links may still use.
+struct TachiInstance {
+    hosts: &'static [&'static str],
+}
+
+const TACHI_INSTANCES: &[TachiInstance] = &[
+    TachiInstance {
+        hosts: &["boku.tachi.ac", "bokutachi.xyz"],
+    },
+    TachiInstance {
+        hosts: &["kamai.tachi.ac", "kamaitachi.xyz"],
+    },
+];
+
+pub const TACHI_PLAYTYPES: &[TachiPlaytype] = &[
+    TachiPlaytype {
+        key: "bms/7K",
+        instance: "boku.tachi.ac",
+        name: "BMS 7K",
+        algorithm: "sieglinde",
+    },
+    TachiPlaytype {
+        key: "bms/14K",
+        instance: "boku.tachi.ac",
+        name: "BMS 14K",
+        algorithm: "sieglinde",
+    },
+    TachiPlaytype {
+        key: "pms/Controller",
+        instance: "boku.tachi.ac",
+        name: "PMS Controller",
+        algorithm: "sieglinde",
+    },
+    TachiPlaytype {
+        key: "pms/Keyboard",
+        instance: "boku.tachi.ac",
+        name: "PMS Keyboard",
+        algorithm: "sieglinde",
+    },
+    TachiPlaytype {
+        key: "iidx/SP",
+        instance: "kamai.tachi.ac",
+        name: "IIDX SP",
+        algorithm: "ktLampRating",
+    },
+    TachiPlaytype {
+        key: "iidx/DP",
+        instance: "kamai.tachi.ac",
+        name: "IIDX DP",
+        algorithm: "ktLampRating",
+    },
+];
+
+const DEFAULT_TACHI_PLAYTYPE: &str = "bms/7K";
+
+impl TachiPlaytype {
+    pub fn get(key: &str) -> Option<&'static TachiPlaytype> {
+        TACHI_PLAYTYPES.iter().find(|playtype| playtype.key == key)
+    }
+
+    /// The playtype set in the config, used when an account doesn't name one.
+    pub fn configured() -> &'static TachiPlaytype {
+        Config::load()
+            .and_then(|config| config.tachi_playtype)
+            .and_then(|key| Self::get(key.as_str()))
+            .or_else(|| Self::get(DEFAULT_TACHI_PLAYTYPE))
+            .expect("Default tachi playtype is missing")
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct TachiUserResponse {
+    body: TachiUser,
+}
+
+#[derive(Deserialize, Debug)]
+struct TachiGameStatsResponse {
+    body: TachiGameStats,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TachiUser {
+    pub id: u32,
+    pub username: String,
+    pub username_lowercase: String,
+    pub playtime: Option<u64>,
+    pub status: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct TachiGameStats {
+    /// Rankings keyed by rating algorithm.
+    pub ranking_data: HashMap<String, TachiRankingData>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TachiRankingData {
+    pub ranking: u32,
+}
+
+impl Tachi {
+    pub fn new(client: Client, api_url: Option<&str>) -> Self {
+        Self {
+            client,
+            api_url: api_url.map(|api_url| api_url.trim_end_matches('/').to_string()),
+        }
+    }
+
+    fn base_url(&self, instance: &str) -> String {
+        match self.api_url {
+            Some(ref api_url) => api_url.clone(),
+            None => format!("https://{instance}"),
+        }
+    }
+
+    async fn fetch_user(&self, instance: &str, user_id: &str) -> Option<Response> {
+        let api_url = format!("{}/api/v1/users/{}", self.base_url(instance), user_id);
+
+        self.client.get(api_url).send().await.ok()
+    }
+
+    async fn fetch_game_stats(
+        &self,
+        instance: &str,
+        user_id: &str,
+        playtype: &TachiPlaytype,
+    ) -> Option<Response> {
+        let api_url = format!(
+            "{}/api/v1/users/{}/games/{}",
+            self.base_url(instance),
+            user_id,
+            playtype.key
+        );
+
+        self.client.get(api_url).send().await.ok()
+    }
+
+    /// Splits an identifier, see [`Tachi::get_user`], into its instance, user and playtype.
+    fn split_identifier(identifier: &str) -> (Option<&str>, &str, Option<&str>) {
+        // usernames can't contain dots, hosts always do
+        let (instance, identifier) = match identifier.split_once('/') {
+            Some((instance, rest)) if instance.contains('.') => (Some(instance), rest),
+            _ => (None, identifier),
+        };
+
+        match identifier.split_once("/games/") {
+            Some((user, playtype)) => (instance, user, Some(playtype)),
+            None => (instance, identifier, None),
+        }
+    }
+
+    fn parse_user(
+        user_response: &str,
+        user_game_stats_response: &str,
+        instance: &str,
+        playtype: &TachiPlaytype,
+    ) -> Option<User> {
+        let user_response = serde_json::from_str::<TachiUserResponse>(user_response)
+            .ok()?
+            .body;
+
+        let user_game_stats_response =
+            serde_json::from_str::<TachiGameStatsResponse>(user_game_stats_response)
+                .ok()?
+                .body;
+
+        let link = format!(
+            "https://{}/u/{}/games/{}",
+            instance, user_response.username_lowercase, playtype.key
+        );
+
+        let ranks = Ranks {
+            global: user_game_stats_response
+                .ranking_data
+                .get(playtype.algorithm)
+                .map(|ranking| ranking.ranking),
+            country: None,
+        };
+
+        let playtime = user_response
+            .playtime
+            .map(|playtime| (playtime / 3_000_000) as u32);
+
+        Some(User {
+            game: Game::BMS,
+            user_id: user_response.id,
+            username: user_response.username,
+            country: None,
+            avatar_url: format!("https://{}/api/v1/users/{}/pfp", instance, user_response.id),
+            link,
+            ranks,
+            playtime,
+            level: None,
+            rating: None,
+            variant: Some(playtype.key.to_string()),
+            instance: Some(instance.to_string()),
+            profile_text: Some(user_response.status.unwrap_or_default()),
+        })
+    }
+}
+
+#[serenity::async_trait]
+impl GameProvider for Tachi {
+    fn game(&self) -> Game {
+        Game::BMS
+    }
+
+    fn parse_identifier(&self, account: &str) -> Option<String> {
+        let (instance, user) = TACHI_INSTANCES.iter().find_map(|instance| {
+            path_identifier(account, instance.hosts, "u").map(|user| (instance.hosts[0], user))
+        })?;
+
+        // links to a game page keep their playtype, `u/<user>/games/<game>/<playtype>`
+        let playtype = account
+            .split_once(&format!("/u/{user}/games/"))
+            .map(|(_, playtype)| playtype.trim_end_matches('/'));
+
+        Some(match playtype {
+            Some(playtype) => format!("{instance}/{user}/games/{playtype}"),
+            None => format!("{instance}/{user}"),
+        })
+    }
+
+    fn identifier_from_username(&self, username: &str) -> Option<String> {
+        // the tachi api accepts usernames wherever it takes a user id
+        Some(username.to_string())
+    }
+
+    fn stored_identifier(&self, account: &StoredAccount) -> String {
+        let mut identifier = match account.instance {
+            Some(instance) => format!("{instance}/{}", account.player_id),
+            None => account.player_id.to_string(),
+        };
+        if let Some(playtype) = account.variant {
+            identifier += &format!("/games/{playtype}");
+        }
+
+        identifier
+    }
+
+    /// Identifiers look like `[<instance>/]<user>[/games/<game>/<playtype>]`, without an
+    /// instance the one tracking the playtype is used.
+    async fn get_user(&self, identifier: &str) -> Option<User> {
+        let (instance, user_id, playtype) = Self::split_identifier(identifier);
+        let playtype = match playtype {
+            Some(key) => TachiPlaytype::get(key)?,
+            None => TachiPlaytype::configured(),
+        };
+        let instance = instance.unwrap_or(playtype.instance);
+
+        let user_response = self.fetch_user(instance, user_id).await?;
+        let user_response_text = user_response.text().await.ok()?;
+
+        let game_stats_response = self.fetch_game_stats(instance, user_id, playtype).await?;
+        let game_stats_response_text = game_stats_response.text().await.ok()?;
+
+        Self::parse_user(
+            &user_response_text,
+            &game_stats_response_text,
+            instance,
+            playtype,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_api::mock_server;
+
+    fn tachi(api_url: Option<&str>) -> Tachi {
+        Tachi::new(Client::new(), api_url)
+    }
+
+    #[test]
+    fn parses_profile_links() {
+        let tachi = tachi(None);
+
+        assert_eq!(
+            tachi.parse_identifier("https://boku.tachi.ac/u/someone"),
+            Some("boku.tachi.ac/someone".to_string())
+        );
+        assert_eq!(
+            tachi.parse_identifier("https://kamai.tachi.ac/u/someone/games/iidx/SP/"),
+            Some("kamai.tachi.ac/someone/games/iidx/SP".to_string())
+        );
+        assert_eq!(tachi.parse_identifier("https://osu.ppy.sh/users/2"), None);
+    }
+
+    #[test]
+    fn old_domains_resolve_to_their_instance() {
+        let tachi = tachi(None);
+
+        assert_eq!(
+            tachi.parse_identifier("bokutachi.xyz/u/someone/games/bms/7K"),
+            Some("boku.tachi.ac/someone/games/bms/7K".to_string())
+        );
+        assert_eq!(
+            tachi.parse_identifier("http://kamaitachi.xyz/u/someone"),
+            Some("kamai.tachi.ac/someone".to_string())
+        );
+    }
+
+    #[test]
+    fn splits_identifiers() {
+        assert_eq!(
+            Tachi::split_identifier("kamai.tachi.ac/someone/games/iidx/DP"),
+            (Some("kamai.tachi.ac"), "someone", Some("iidx/DP"))
+        );
+        assert_eq!(
+            Tachi::split_identifier("boku.tachi.ac/12"),
+            (Some("boku.tachi.ac"), "12", None)
+        );
+        assert_eq!(
+            Tachi::split_identifier("someone/games/pms/Keyboard"),
+            (None, "someone", Some("pms/Keyboard"))
+        );
+        assert_eq!(Tachi::split_identifier("someone"), (None, "someone", None));
+    }
+
+    #[test]
+    fn stored_identifiers_round_trip() {
+        let identifier = tachi(None).stored_identifier(&StoredAccount {
+            player_id: 12,
+            username: "someone",
+            variant: Some("iidx/SP"),
+            instance: Some("kamai.tachi.ac"),
+        });
+
+        assert_eq!(identifier, "kamai.tachi.ac/12/games/iidx/SP");
+        assert_eq!(
+            Tachi::split_identifier(&identifier),
+            (Some("kamai.tachi.ac"), "12", Some("iidx/SP"))
+        );
+    }
+
+    #[tokio::test]
+    async fn fetches_the_linked_playtype() {
+        let api_url = mock_server::serve(&[
+            (
+                "/api/v1/users/someone",
+                r#"{"body": {"id": 12, "username": "Someone", "usernameLowercase": "someone", "status": "hi"}}"#,
+            ),
+            (
+                "/api/v1/users/someone/games/iidx/SP",
+                r#"{"body": {"rankingData": {"ktLampRating": {"ranking": 3}}}}"#,
+            ),
+        ])
+        .await;
+
+        let tachi = tachi(Some(&api_url));
+        let identifier = tachi
+            .parse_identifier("https://kamai.tachi.ac/u/someone/games/iidx/SP")
+            .unwrap();
+        let user = tachi.get_user(&identifier).await.unwrap();
+
+        assert_eq!(user.user_id, 12);
+        assert_eq!(user.username, "Someone");
+        assert_eq!(user.ranks.global, Some(3));
+        assert_eq!(user.variant.as_deref(), Some("iidx/SP"));
+        assert_eq!(user.instance.as_deref(), Some("kamai.tachi.ac"));
+        assert_eq!(user.link, "https://kamai.tachi.ac/u/someone/games/iidx/SP");
+    }
+
+    #[tokio::test]
+    async fn unknown_playtypes_are_not_fetched() {
+        let tachi = tachi(Some("http://127.0.0.1:9"));
+
+        assert!(tachi.get_user("someone/games/bms/5K").await.is_none());
+    }
+}
@@ -1,19 +1,30 @@
+pub mod audit;
 pub mod commands;
 pub mod config;
+pub mod database;
 pub mod game_api;
+pub mod ranks;
 pub mod user;
 pub mod verification;
 
-use serenity::builder::{CreateInteractionResponse, CreateInteractionResponseMessage};
+use serenity::builder::{
+    CreateActionRow, CreateInputText, CreateInteractionResponse, CreateInteractionResponseMessage,
+    CreateModal,
+};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 
 use regex::Regex;
 
+use crate::audit::{AuditAction, AuditEntry};
+use crate::game_api::GameProviders;
 use crate::verification::PendingVerifications;
-use commands::{config_command, list_command, verify_command, remove_user_command};
-use verify_command::VerificationError;
+use commands::{
+    audit_command, config_command, leaderboard_command, list_command, profile_command,
+    remove_user_command, verify_command,
+};
 use remove_user_command::RemoveUserError;
+use verify_command::VerificationError;
 
 use std::str::FromStr;
 
@@ -51,27 +62,26 @@ impl FromStr for Args {
     type Err = ParseArgsError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s: Vec<String> = s
-            .trim()
-            .to_lowercase()
-            .split(" ")
-            .map(|s| s.to_string())
-            .collect();
+        // only the command is case insensitive, usernames in the arguments are not
+        let s: Vec<String> = s.trim().split(" ").map(|s| s.to_string()).collect();
 
         Ok(Self {
-            cmd: s[0].clone(),
+            cmd: s[0].to_lowercase(),
             args: s[1..].to_vec(),
         })
     }
 }
 
+/// Reply to buttons of a request that was already handled, expired or couldn't be restored.
+const NO_LONGER_PENDING: &str = "This verification request is no longer pending";
+
 #[derive(Default)]
 pub struct Handler;
 
 #[serenity::async_trait]
 impl EventHandler for Handler {
     async fn message(&self, ctx: Context, message: Message) {
-        if message.content.len() == 0 {
+        if message.content.is_empty() {
             return;
         }
 
@@ -79,47 +89,55 @@ impl EventHandler for Handler {
             return;
         }
 
+        if !config::Config::load().unwrap_or_default().prefix_commands {
+            return;
+        }
+
         let args: Args = message.content[1..].parse().expect("This cannot fail");
 
         tokio::spawn(async move {
             if let Ok(member) = message.member(&ctx.http).await {
                 match args.cmd() {
                     "verify" => {
-                        let result =
-                            verify_command::execute(&ctx, &message.channel_id, member, args).await;
+                        let result = match args.arg(0) {
+                            Some(account) => {
+                                verify_command::execute(
+                                    &ctx,
+                                    &message.channel_id,
+                                    member,
+                                    None,
+                                    account,
+                                )
+                                .await
+                            }
+                            None => Err(VerificationError::NoArgumentSupplied),
+                        };
 
                         match result {
-                            Err(VerificationError::UserAlreadyExists(e))
-                            | Err(VerificationError::NotConfigured(e))
-                            | Err(VerificationError::VerificationFailed(e)) => {
-                                if let Err(e) = message.channel_id.say(&ctx.http, e).await {
-                                    eprintln!("Could not send message {e}");
-                                }
-                            }
-                            Err(VerificationError::NoArgumentSupplied) => {
-                                if let Err(e) = message
-                                    .channel_id
-                                    .say(&ctx.http, "Please provide a profile link to verify.")
-                                    .await
+                            // prefix commands outside the verification channel are ignored
+                            Err(VerificationError::WrongChannel(_)) => (),
+                            Err(e) => {
+                                if let Some(e) = e.message()
+                                    && let Err(e) = message.channel_id.say(&ctx.http, e).await
                                 {
                                     eprintln!("Could not send message {e}");
                                 }
                             }
-                            _ => return,
+                            Ok(()) => (),
                         }
                     }
                     "list" => {
                         let result =
                             list_command::execute(&ctx, &message.channel_id, &member, args).await;
 
-                        if let Err(e) = result {
-                            if let Err(e) = message.channel_id.say(&ctx.http, e).await {
-                                eprintln!("Could not send message {e}");
-                            }
+                        if let Err(e) = result
+                            && let Err(e) = message.channel_id.say(&ctx.http, e).await
+                        {
+                            eprintln!("Could not send message {e}");
                         }
                     }
-                    _ => return,
-                };
+                    _ => (),
+                }
             }
         });
     }
@@ -127,21 +145,48 @@ impl EventHandler for Handler {
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
         match interaction {
             Interaction::Command(command) => {
-                let content = match command.data.name.as_str() {
-                    "config" => config_command::execute(&command.data).await,
+                let data = match command.data.name.as_str() {
+                    "config" => CreateInteractionResponseMessage::new()
+                        .content(config_command::execute(&ctx, &command).await),
                     "remove_user" => {
-                        let result = remove_user_command::execute(&command.data).await;
-                        match result {
+                        let result = remove_user_command::execute(&ctx, &command).await;
+                        let content = match result {
                             Ok(s) => s,
-                            Err(RemoveUserError::DatabaseFailure) => "Could not remove user from database".to_string(),
-                            Err(RemoveUserError::InvalidGame(game)) => format!("{game} is not a valid game"),
-                            Err(RemoveUserError::InvalidOption) => "Options were not inputted correctly".to_string(),
+                            Err(RemoveUserError::DatabaseFailure) => {
+                                "Could not remove user from database".to_string()
+                            }
+                            Err(RemoveUserError::InvalidGame(game)) => {
+                                format!("{game} is not a valid game")
+                            }
+                            Err(RemoveUserError::InvalidOption) => {
+                                "Options were not inputted correctly".to_string()
+                            }
+                        };
+                        CreateInteractionResponseMessage::new().content(content)
+                    }
+                    "leaderboard" => leaderboard_command::execute(&command.data)
+                        .unwrap_or_else(|e| CreateInteractionResponseMessage::new().content(e)),
+                    "list" => list_command::run(&command.data),
+                    "audit" => audit_command::execute(&command.data).unwrap_or_else(|e| {
+                        CreateInteractionResponseMessage::new()
+                            .content(e)
+                            .ephemeral(true)
+                    }),
+                    "verify" => {
+                        if let Err(e) = verify_command::run(&ctx, &command).await {
+                            eprintln!("{e}");
+                        }
+                        return;
+                    }
+                    "profile" => {
+                        if let Err(e) = profile_command::execute(&ctx, &command).await {
+                            eprintln!("{e}");
                         }
+                        return;
                     }
                     _ => return,
                 };
 
-                let data = CreateInteractionResponseMessage::new().content(content);
                 let response = CreateInteractionResponse::Message(data);
                 if let Err(e) = command.create_response(&ctx.http, response).await {
                     eprintln!("Could not create response for interaction: {}", e);
@@ -149,42 +194,121 @@ impl EventHandler for Handler {
             }
 
             Interaction::Component(component) => {
+                let page_change = if component.data.custom_id.starts_with("leaderboard ") {
+                    Some(leaderboard_command::change_page as fn(&[&str]) -> _)
+                } else if component.data.custom_id.starts_with("audit ") {
+                    Some(audit_command::change_page as fn(&[&str]) -> _)
+                } else {
+                    None
+                };
+
+                if let Some(change_page) = page_change {
+                    let id = component.data.custom_id.split(" ").collect::<Vec<&str>>();
+                    let response = match change_page(&id) {
+                        Ok(data) => CreateInteractionResponse::UpdateMessage(data),
+                        Err(e) => CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(e)
+                                .ephemeral(true),
+                        ),
+                    };
+
+                    if let Err(e) = component.create_response(&ctx.http, response).await {
+                        eprintln!("Could not create response for interaction: {}", e);
+                    }
+                    return;
+                }
+
                 let mut data = ctx.data.write().await;
-                let guild_id = data.get::<GuildKey>().unwrap().clone();
+                let guild_id = *data.get::<GuildKey>().unwrap();
 
                 let id = component.data.custom_id.clone();
                 let id = id.split(" ").collect::<Vec<&str>>();
                 if let ComponentInteractionDataKind::Button = component.data.kind {
+                    let providers = data.get::<GameProviders>().unwrap().clone();
                     let verifications = data.get_mut::<PendingVerifications>().unwrap();
 
-                    let verification =
-                        match verifications.get_mut(&id[1].parse::<u64>().expect("Invalid Id")) {
-                            Some(verification) => verification,
-                            None => return,
-                        };
+                    // requests that could not be restored at startup are retried when used
+                    let verification_id = id[1].parse::<u64>().expect("Invalid Id");
+                    if let Err(e) = verifications
+                        .reload(&ctx, &guild_id, &providers, verification_id)
+                        .await
+                    {
+                        let response = CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(format!(
+                                    "This verification request could not be loaded, try again later: {e}"
+                                ))
+                                .ephemeral(true),
+                        );
+                        if let Err(e) = component.create_response(&ctx.http, response).await {
+                            eprintln!("Could not create response for interaction: {}", e);
+                        }
+                        return;
+                    }
+
+                    let verification = match verifications.get_mut(&verification_id) {
+                        Some(verification) => verification,
+                        None => {
+                            let response = CreateInteractionResponse::Message(
+                                CreateInteractionResponseMessage::new()
+                                    .content(NO_LONGER_PENDING)
+                                    .ephemeral(true),
+                            );
+                            if let Err(e) = component.create_response(&ctx.http, response).await {
+                                eprintln!("Could not create response for interaction: {}", e);
+                            }
+                            return;
+                        }
+                    };
+
+                    let account = format!(
+                        "{}: {} ({})",
+                        verification.user.game, verification.user.username, verification.user.link
+                    );
 
                     let content = match id[0] {
                         "verify" => match verification.apply(&ctx, &guild_id).await {
-                            Ok(()) => Ok(format!(
-                                "Verified user: {}",
-                                &verification.discord_user.user.name
-                            )),
-                            Err(e) => Err(e),
-                        },
-                        "deny" => match verification.deny(&ctx).await {
-                            Ok(()) => Ok(format!(
-                                "Declined user: {}",
-                                &verification.discord_user.user.name
-                            )),
+                            Ok(()) => {
+                                AuditEntry::new(component.user.id, AuditAction::Approve)
+                                    .target(verification.discord_user.user.id)
+                                    .after(account)
+                                    .record(&ctx.http)
+                                    .await;
+
+                                Ok(format!(
+                                    "Verified user: {}",
+                                    &verification.discord_user.user.name
+                                ))
+                            }
                             Err(e) => Err(e),
                         },
+                        "deny" => {
+                            // the request is only denied once the reason is submitted
+                            let reason =
+                                CreateInputText::new(InputTextStyle::Paragraph, "Reason", "reason")
+                                    .placeholder("Shown to the applicant")
+                                    .max_length(1000);
+
+                            let modal = CreateModal::new(
+                                component.data.custom_id.clone(),
+                                "Decline verification request",
+                            )
+                            .components(vec![CreateActionRow::InputText(reason)]);
+
+                            let response = CreateInteractionResponse::Modal(modal);
+                            if let Err(e) = component.create_response(&ctx.http, response).await {
+                                eprintln!("Could not create response for interaction: {}", e);
+                            }
+                            return;
+                        }
 
                         _ => Err("Error: Invalid Id".to_string()),
                     };
 
                     let data = match content {
                         Ok(content) => {
-                            verifications.remove(&id[1].parse::<u64>().expect("Invalid Id"));
+                            verifications.discard(verification_id);
                             CreateInteractionResponseMessage::new().content(content)
                         }
                         Err(e) => CreateInteractionResponseMessage::new().content(e),
@@ -205,9 +329,21 @@ impl EventHandler for Handler {
 
                     let verifications = data.get_mut::<PendingVerifications>().unwrap();
 
-                    let mut verification = verifications
-                        .get_mut(&id[1].parse::<u64>().expect("Invalid Id"))
-                        .expect("Id could not be found in pending verifications");
+                    let verification_id = id[1].parse::<u64>().expect("Invalid Id");
+                    let verification = match verifications.get_mut(&verification_id) {
+                        Some(verification) => verification,
+                        None => {
+                            let message = CreateInteractionResponseMessage::new().content(
+                                "This verification request has expired, please verify again",
+                            );
+
+                            let response = CreateInteractionResponse::Message(message);
+                            if let Err(e) = component.create_response(&ctx.http, response).await {
+                                eprintln!("Could not create response for interaction: {}", e);
+                            }
+                            return;
+                        }
+                    };
 
                     let country =
                         remove_emojis(&guild_id.role(&ctx.http, values[0]).await.unwrap().name);
@@ -229,7 +365,7 @@ impl EventHandler for Handler {
                         return;
                     };
 
-                    if !(component.user.id == verification.discord_user.user.id) {
+                    if component.user.id != verification.discord_user.user.id {
                         let message = CreateInteractionResponseMessage::new()
                             .content("Only the user who made the verification request can select the country here");
 
@@ -243,7 +379,7 @@ impl EventHandler for Handler {
 
                     verify_command::verify_user(
                         &ctx,
-                        &mut verification,
+                        verification,
                         &component.channel_id,
                         &admin_channel,
                     )
@@ -255,6 +391,10 @@ impl EventHandler for Handler {
                         &verification.discord_user
                     ));
 
+                    if let Err(e) = verifications.save(verification_id) {
+                        eprintln!("{e}");
+                    }
+
                     let response = CreateInteractionResponse::Message(message);
                     component
                         .create_response(&ctx.http, response)
@@ -264,22 +404,161 @@ impl EventHandler for Handler {
                     component.message.delete(&ctx.http).await.unwrap();
                 }
             }
+            Interaction::Autocomplete(autocomplete) => {
+                let response = match autocomplete.data.name.as_str() {
+                    "remove_user" => remove_user_command::autocomplete(&autocomplete.data),
+                    _ => return,
+                };
+
+                let response = CreateInteractionResponse::Autocomplete(response);
+                if let Err(e) = autocomplete.create_response(&ctx.http, response).await {
+                    eprintln!("Could not create response for interaction: {}", e);
+                }
+            }
+            Interaction::Modal(modal) => {
+                let id = modal.data.custom_id.split(" ").collect::<Vec<&str>>();
+                if id[0] != "deny" {
+                    return;
+                }
+
+                let reason = modal
+                    .data
+                    .components
+                    .iter()
+                    .flat_map(|row| row.components.iter())
+                    .find_map(|component| match component {
+                        ActionRowComponent::InputText(input) if input.custom_id == "reason" => {
+                            input.value.clone()
+                        }
+                        _ => None,
+                    })
+                    .unwrap_or_default();
+
+                let mut data = ctx.data.write().await;
+                let verifications = data.get_mut::<PendingVerifications>().unwrap();
+
+                let verification_id = id[1].parse::<u64>().expect("Invalid Id");
+                let verification = match verifications.get_mut(&verification_id) {
+                    Some(verification) => verification,
+                    None => {
+                        let response = CreateInteractionResponse::Message(
+                            CreateInteractionResponseMessage::new()
+                                .content(NO_LONGER_PENDING)
+                                .ephemeral(true),
+                        );
+                        if let Err(e) = modal.create_response(&ctx.http, response).await {
+                            eprintln!("Could not create response for interaction: {}", e);
+                        }
+                        return;
+                    }
+                };
+
+                let account = format!(
+                    "{}: {} ({})",
+                    verification.user.game, verification.user.username, verification.user.link
+                );
+
+                let content = match verification.deny(&ctx, &reason).await {
+                    Ok(notified) => {
+                        AuditEntry::new(modal.user.id, AuditAction::Deny)
+                            .target(verification.discord_user.user.id)
+                            .before(account)
+                            .after(reason)
+                            .record(&ctx.http)
+                            .await;
+
+                        let mut content =
+                            format!("Declined user: {}", &verification.discord_user.user.name);
+                        if !notified {
+                            content += " (their DMs are closed, the reason is only shown in the status message)";
+                        }
+
+                        verifications.discard(verification_id);
+                        content
+                    }
+                    Err(e) => e,
+                };
+
+                let response = CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new().content(content),
+                );
+                if let Err(e) = modal.create_response(&ctx.http, response).await {
+                    eprintln!("Could not create response for interaction: {}", e);
+                }
+            }
             _ => eprintln!("Not yet implemented"),
         }
     }
 
+    async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
+        let guild_id = *ctx.data.read().await.get::<GuildKey>().unwrap();
+        if new_member.guild_id != guild_id {
+            return;
+        }
+
+        match verification::restore_member(&ctx, &new_member).await {
+            Ok(true) => println!(
+                "Restored roles of returning member {}",
+                new_member.user.name
+            ),
+            Ok(false) => {}
+            Err(e) => eprintln!("Could not restore member {}: {e}", new_member.user.name),
+        }
+    }
+
+    async fn guild_member_removal(
+        &self,
+        ctx: Context,
+        guild_id: GuildId,
+        user: User,
+        _member_data_if_available: Option<Member>,
+    ) {
+        if guild_id != *ctx.data.read().await.get::<GuildKey>().unwrap() {
+            return;
+        }
+
+        if user::set_active(user.id.get(), false).is_err() {
+            eprintln!("Could not mark the accounts of {} as inactive", user.name);
+        }
+    }
+
     async fn ready(&self, ctx: Context, data_about_bot: Ready) {
         println!("session with id: {} started", data_about_bot.session_id);
 
-        let commands = vec![commands::config_command::register(), commands::remove_user_command::register()];
-
-        let data = ctx.data.read().await;
-        let guild_id = data.get::<GuildKey>().expect("No guild key found");
+        let commands = vec![
+            commands::config_command::register(),
+            commands::remove_user_command::register(),
+            commands::leaderboard_command::register(),
+            commands::profile_command::register(),
+            commands::verify_command::register(),
+            commands::list_command::register(),
+            commands::audit_command::register(),
+        ];
+
+        let (guild_id, providers) = {
+            let data = ctx.data.read().await;
+            let guild_id = *data.get::<GuildKey>().expect("No guild key found");
+            let providers = data
+                .get::<GameProviders>()
+                .expect("No game providers found")
+                .clone();
+            (guild_id, providers)
+        };
 
         guild_id
             .set_commands(&ctx.http, commands)
             .await
             .expect("Could not set guild commands");
+
+        match PendingVerifications::load_saved(&ctx, &guild_id, &providers).await {
+            Ok(saved) => {
+                let mut data = ctx.data.write().await;
+                if let Some(verifications) = data.get_mut::<PendingVerifications>() {
+                    verifications.restore(saved);
+                }
+            }
+            Err(e) => eprintln!("Could not restore pending verifications: {e}"),
+        }
     }
 }
 
@@ -2,53 +2,89 @@ use serenity::model::prelude::*;
 use serenity::prelude::*;
 
 use sevenkey_global_bot::GuildKey;
-use sevenkey_global_bot::game_api::Osu;
-use sevenkey_global_bot::verification::PendingVerifications;
+use sevenkey_global_bot::config::Config;
+use sevenkey_global_bot::game_api::{DMJam, Etterna, GameProviders, Malody, Osu, Quaver, Tachi};
+use sevenkey_global_bot::verification::{self, OwnershipChallenges, PendingVerifications};
+use sevenkey_global_bot::{database, ranks};
 
 use std::env;
+use std::sync::Arc;
 
 use rusqlite::Connection;
 
 #[tokio::main()]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let token = env::var("DISCORD_TOKEN")?;
-    let mut client = Client::builder(
-        token,
-        GatewayIntents::default() | GatewayIntents::MESSAGE_CONTENT,
-    )
-    .event_handler(sevenkey_global_bot::Handler)
-    .await?;
+    // member events are needed to restore the roles of members who rejoin
+    let intents = GatewayIntents::default() | GatewayIntents::GUILD_MEMBERS;
+    let intents = if Config::load().unwrap_or_default().prefix_commands {
+        intents | GatewayIntents::MESSAGE_CONTENT
+    } else {
+        intents
+    };
+
+    let mut client = Client::builder(token, intents)
+        .event_handler(sevenkey_global_bot::Handler)
+        .await?;
 
     let client_id = env::var("OSU_API_ID")?;
     let client_secret = env::var("OSU_API_SECRET")?;
     let guild_id = GuildId::new(env::var("GUILD_ID")?.parse::<u64>()?);
 
     let req_client = reqwest::Client::new();
-    let osu = Osu::build(req_client, &client_id, &client_secret)
-        .await
-        .ok_or("Could not build osu client")?;
+    let osu = Osu::build(
+        req_client.clone(),
+        &client_id,
+        &client_secret,
+        &api_url("OSU_API_URL", Osu::API_URL),
+    )
+    .await
+    .ok_or("Could not build osu client")?;
+
+    let mut providers = GameProviders::default();
+    providers.register(osu);
+    providers.register(Quaver::new(
+        req_client.clone(),
+        &api_url("QUAVER_API_URL", Quaver::API_URL),
+    ));
+    providers.register(Tachi::new(
+        req_client.clone(),
+        env::var("TACHI_API_URL").ok().as_deref(),
+    ));
+    providers.register(DMJam::new(
+        req_client.clone(),
+        &api_url("DMJAM_API_URL", DMJam::API_URL),
+    ));
+    providers.register(Etterna::new(
+        req_client.clone(),
+        &api_url("ETTERNA_API_URL", Etterna::API_URL),
+    ));
+    providers.register(Malody::new(
+        req_client,
+        &api_url("MALODY_API_URL", Malody::API_URL),
+    ));
 
     {
         let mut data = client.data.write().await;
         data.insert::<PendingVerifications>(PendingVerifications::default());
+        data.insert::<OwnershipChallenges>(OwnershipChallenges::default());
         data.insert::<GuildKey>(guild_id);
-        data.insert::<Osu>(osu);
+        data.insert::<GameProviders>(Arc::new(providers));
     }
 
     {
-        let conn = Connection::open("users.db")?;
-        conn.execute(
-            "create table if not exists users (
-                 discord_id integer primary key,
-                 game text not null,
-                 player_id integer not null,
-                 username text not null,
-                 country text not null
-             )",
-            (),
-        )?;
+        let mut conn = Connection::open("users.db")?;
+        database::migrate(&mut conn)?;
     }
 
+    verification::spawn_expiry_task(client.data.clone(), client.http.clone());
+    ranks::spawn_refresh_task(client.data.clone(), client.http.clone());
+
     client.start().await?;
     Ok(())
 }
+
+/// The base url a game client sends its requests to, overridable to run against a mock server.
+fn api_url(var: &str, default: &str) -> String {
+    env::var(var).unwrap_or_else(|_| default.to_string())
+}
@@ -0,0 +1,203 @@
+use serenity::builder::EditMember;
+use serenity::http::Http;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+use rusqlite::{Connection, params};
+
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::GuildKey;
+use crate::config::Config;
+use crate::game_api::GameProviders;
+use crate::user::{self, Game, LinkedAccount, User};
+use crate::verification::unix_now;
+
+// spacing between api calls so a refresh does not hammer the game servers
+const REQUEST_DELAY: Duration = Duration::from_millis(500);
+
+fn update_account(account: &LinkedAccount, user: &User) -> Result<(), String> {
+    let conn = Connection::open("users.db").map_err(|_| "Database failure")?;
+
+    conn.execute(
+        "UPDATE users SET username=?1, global_rank=?2, country_rank=?3
+         WHERE discord_id=?4 AND game=?5 AND variant IS ?6",
+        params![
+            user.username,
+            user.ranks.global,
+            user.ranks.country,
+            account.discord_id,
+            account.game,
+            account.variant
+        ],
+    )
+    .map_err(|e| format!("Could not update account: {e}"))?;
+
+    Ok(())
+}
+
+/// Gives the member the rank bracket role their global rank in the game falls into and takes
+/// away the other brackets of that game, or of that variant of it.
+pub async fn sync_rank_roles(
+    http: &Http,
+    member: &Member,
+    game: Game,
+    variant: Option<&str>,
+    rank: Option<u32>,
+) -> Result<(), String> {
+    let config = Config::load().unwrap_or_default();
+    let ranking = game.ranking(variant);
+    let Some(brackets) = config.rank_roles.get(&ranking) else {
+        return Ok(());
+    };
+
+    let wanted = config.rank_role(&ranking, rank);
+
+    let outdated = brackets
+        .iter()
+        .map(|bracket| bracket.role)
+        .filter(|role| Some(*role) != wanted && member.roles.contains(role))
+        .collect::<Vec<RoleId>>();
+
+    if !outdated.is_empty() {
+        member
+            .remove_roles(http, &outdated)
+            .await
+            .map_err(|e| format!("Could not remove rank roles: {e}"))?;
+    }
+
+    if let Some(wanted) = wanted
+        && !member.roles.contains(&wanted)
+    {
+        member
+            .add_role(http, wanted)
+            .await
+            .map_err(|e| format!("Could not add rank role: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Fetches every linked account again, recording its ranks, picking up renames, moving
+/// members between rank bracket roles and updating nicknames rendered from the account.
+pub async fn refresh_all(
+    http: &Http,
+    guild_id: GuildId,
+    providers: &GameProviders,
+) -> Result<(), String> {
+    let recorded_at = unix_now();
+    let config = Config::load().unwrap_or_default();
+
+    let accounts = user::linked_accounts(None).map_err(|_| "Database failure")?;
+    for account in accounts {
+        let Ok(game) = Game::from_str(&account.game) else {
+            continue;
+        };
+
+        let Some(provider) = providers.get(game) else {
+            continue;
+        };
+
+        let identifier = provider.stored_identifier(&account.stored());
+        let user = provider.get_user(&identifier).await;
+        tokio::time::sleep(REQUEST_DELAY).await;
+
+        let Some(user) = user else {
+            eprintln!(
+                "Could not refresh {} account {} of <@{}>",
+                account.game, account.player_id, account.discord_id
+            );
+            continue;
+        };
+
+        if user
+            .save_rank_snapshot(account.discord_id, recorded_at)
+            .is_err()
+        {
+            eprintln!("Could not save ranks of {}", user.username);
+        }
+
+        if let Err(e) = update_account(&account, &user) {
+            eprintln!("{e}");
+        }
+
+        let previous_nickname = config.nickname(
+            game,
+            &account.username,
+            &account.country,
+            account.global_rank,
+        );
+        let nickname = config.nickname(game, &user.username, &account.country, user.ranks.global);
+        let rename = previous_nickname
+            .zip(nickname)
+            .filter(|(previous, new)| previous != new);
+
+        let ranking = game.ranking(account.variant.as_deref());
+        if !config.rank_roles.contains_key(&ranking) && rename.is_none() {
+            continue;
+        }
+
+        let member = match guild_id.member(http, account.discord_id).await {
+            Ok(member) => member,
+            Err(e) => {
+                eprintln!("Could not get member <@{}>: {e}", account.discord_id);
+                continue;
+            }
+        };
+
+        if let Err(e) = sync_rank_roles(
+            http,
+            &member,
+            game,
+            account.variant.as_deref(),
+            user.ranks.global,
+        )
+        .await
+        {
+            eprintln!("{e}");
+        }
+
+        // nicknames the member changed themselves are left alone
+        if let Some((previous, nickname)) = rename
+            && member.nick.as_deref() == Some(previous.as_str())
+            && let Err(e) = guild_id
+                .edit_member(
+                    http,
+                    account.discord_id,
+                    EditMember::new().nickname(nickname),
+                )
+                .await
+        {
+            eprintln!(
+                "Could not update nickname of <@{}>: {e}",
+                account.discord_id
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Periodically refreshes the ranks of all linked accounts, see [`refresh_all`].
+pub fn spawn_refresh_task(data: Arc<RwLock<TypeMap>>, http: Arc<Http>) {
+    tokio::spawn(async move {
+        loop {
+            let interval = Config::load().unwrap_or_default().rank_refresh_interval();
+            tokio::time::sleep(interval).await;
+
+            let (guild_id, providers) = {
+                let data = data.read().await;
+                match (data.get::<GuildKey>(), data.get::<GameProviders>()) {
+                    (Some(guild_id), Some(providers)) => (*guild_id, providers.clone()),
+                    _ => continue,
+                }
+            };
+
+            if let Err(e) = refresh_all(&http, guild_id, &providers).await {
+                eprintln!("Could not refresh ranks: {e}");
+            }
+        }
+    });
+}
@@ -4,11 +4,16 @@ use serenity::builder::CreateEmbed;
 use std::fmt;
 use std::str::FromStr;
 
+use crate::game_api::{StoredAccount, TachiPlaytype};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Game {
     Osu,
     Quaver,
     BMS,
     DMJam,
+    Etterna,
+    Malody,
 }
 
 impl fmt::Display for Game {
@@ -18,6 +23,48 @@ impl fmt::Display for Game {
             Self::Quaver => write!(f, "quaver"),
             Self::BMS => write!(f, "bms"),
             Self::DMJam => write!(f, "dmjam"),
+            Self::Etterna => write!(f, "etterna"),
+            Self::Malody => write!(f, "malody"),
+        }
+    }
+}
+
+impl Game {
+    pub const ALL: [Game; 6] = [
+        Self::Osu,
+        Self::Quaver,
+        Self::BMS,
+        Self::DMJam,
+        Self::Etterna,
+        Self::Malody,
+    ];
+
+    /// Name of the game as shown in profile embeds.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Self::Osu => "Osu",
+            Self::Quaver => "Quaver 7k",
+            Self::BMS => "Tachi",
+            Self::DMJam => "DMJam",
+            Self::Etterna => "Etterna",
+            Self::Malody => "Malody 7k",
+        }
+    }
+
+    /// Title of the part of the game an account was verified for, like "IIDX SP" for a
+    /// Tachi playtype, or of the game itself.
+    pub fn variant_title(&self, variant: Option<&str>) -> &'static str {
+        variant
+            .and_then(TachiPlaytype::get)
+            .map_or(self.title(), |playtype| playtype.name)
+    }
+
+    /// The ranking an account's ranks belong to, `game` or `game/variant` for games whose
+    /// variants are ranked separately. Ranks can only be compared within one ranking.
+    pub fn ranking(&self, variant: Option<&str>) -> String {
+        match variant {
+            Some(variant) => format!("{self}/{variant}"),
+            None => self.to_string(),
         }
     }
 }
@@ -34,6 +81,8 @@ impl FromStr for Game {
             "quaver" => Ok(Self::Quaver),
             "bms" => Ok(Self::BMS),
             "dmjam" => Ok(Self::DMJam),
+            "etterna" => Ok(Self::Etterna),
+            "malody" => Ok(Self::Malody),
             _ => Err(ParseGameError),
         }
     }
@@ -55,307 +104,217 @@ pub struct User {
     pub link: String,
     pub playtime: Option<u32>,
     pub level: Option<u32>,
+    /// Skill rating, for games that rate players instead of giving them a level.
+    pub rating: Option<f32>,
+    /// Which part of the game the account was verified for, like the Tachi playtype.
+    pub variant: Option<String>,
+    /// Host of the server the account lives on, for games with several instances.
+    pub instance: Option<String>,
+    /// Free text the player controls on their profile, used for ownership challenges.
+    /// `None` when the game has no such field.
+    pub profile_text: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct OsuUser {
-    pub username: String,
-    pub country: OsuCountry,
-    pub statistics: OsuUserStatistics,
-    pub avatar_url: String,
-    pub id: u32,
-}
-
-#[derive(Deserialize, Debug)]
-struct OsuUserStatistics {
-    pub global_rank: Option<u32>,
-    pub country_rank: Option<u32>,
-    pub play_time: Option<u64>,
-}
-
-#[derive(Deserialize, Debug)]
-struct OsuCountry {
-    pub code: String,
-}
-
-#[derive(Deserialize, Debug)]
-struct QuaverUserResponse {
-    pub user: QuaverUser,
-}
+#[derive(Debug, PartialEq, Eq)]
+pub struct DBSaveError;
 
-#[derive(Deserialize, Debug)]
-struct QuaverUser {
-    pub id: u32,
-    pub username: String,
-    pub avatar_url: String,
-    pub stats_keys7: QuaverUserStatistics,
-    pub country: String,
-}
+/// Whether the member already has at least one verified game account.
+pub fn has_linked_account(discord_user_id: u64) -> Result<bool, DBSaveError> {
+    let conn = Connection::open("users.db").map_err(|_| DBSaveError)?;
 
-#[derive(Deserialize, Debug)]
-struct QuaverUserStatistics {
-    pub ranks: Ranks,
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE discord_id=?1 AND active=1)",
+        [discord_user_id],
+        |row| row.get(0),
+    )
+    .map_err(|_| DBSaveError)
 }
 
-#[derive(Deserialize, Debug)]
-struct TachiUserResponse {
-    body: TachiUser,
-}
+/// Marks every account of the member as active or inactive, returning how many there are.
+pub fn set_active(discord_user_id: u64, active: bool) -> Result<usize, DBSaveError> {
+    let conn = Connection::open("users.db").map_err(|_| DBSaveError)?;
 
-#[derive(Deserialize, Debug)]
-struct TachiGameStatsResponse {
-    body: TachiGameStats,
+    conn.execute(
+        "UPDATE users SET active=?2 WHERE discord_id=?1",
+        params![discord_user_id, active],
+    )
+    .map_err(|_| DBSaveError)
 }
 
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct TachiUser {
-    pub id: u32,
+/// A verified account as stored in the database.
+pub struct LinkedAccount {
+    pub discord_id: u64,
+    pub game: String,
+    pub player_id: u32,
     pub username: String,
-    pub username_lowercase: String,
-    pub playtime: Option<u64>,
-}
-
-#[derive(Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-struct TachiGameStats {
-    pub ranking_data: TachiStupidSection,
+    pub country: String,
+    pub global_rank: Option<u32>,
+    pub variant: Option<String>,
+    pub instance: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
-struct TachiStupidSection {
-    pub sieglinde: TachiRankingData,
+impl LinkedAccount {
+    /// What a [`crate::game_api::GameProvider`] needs to fetch the account again.
+    pub fn stored(&self) -> StoredAccount<'_> {
+        StoredAccount {
+            player_id: self.player_id,
+            username: &self.username,
+            variant: self.variant.as_deref(),
+            instance: self.instance.as_deref(),
+        }
+    }
 }
 
-#[derive(Deserialize, Debug)]
-struct TachiRankingData {
-    pub ranking: u32,
-}
+/// The active linked accounts of one member, or of everyone when `discord_user_id` is `None`.
+pub fn linked_accounts(discord_user_id: Option<u64>) -> Result<Vec<LinkedAccount>, DBSaveError> {
+    let conn = Connection::open("users.db").map_err(|_| DBSaveError)?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT discord_id, game, player_id, username, country, global_rank, variant, instance
+             FROM users WHERE active=1 AND (?1 IS NULL OR discord_id=?1)",
+        )
+        .map_err(|_| DBSaveError)?;
+
+    let rows = stmt
+        .query_map([discord_user_id], |row| {
+            Ok(LinkedAccount {
+                discord_id: row.get(0)?,
+                game: row.get(1)?,
+                player_id: row.get(2)?,
+                username: row.get(3)?,
+                country: row.get(4)?,
+                global_rank: row.get(5)?,
+                variant: row.get(6)?,
+                instance: row.get(7)?,
+            })
+        })
+        .map_err(|_| DBSaveError)?;
 
-#[derive(Deserialize, Debug)]
-struct DMJamUser {
-    player_code: u32,
-    nickname: String,
-    player_ranking: u32,
-    level: u32,
+    Ok(rows.flatten().collect())
 }
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct DBSaveError;
-
 impl User {
     pub fn save_to_database(
         &self,
         discord_user_id: u64,
         country: Option<&str>,
     ) -> Result<(), DBSaveError> {
-        let conn = Connection::open("users.db").map_err(|_| DBSaveError)?;
+        let mut conn = Connection::open("users.db").map_err(|_| DBSaveError)?;
+        let tx = conn.transaction().map_err(|_| DBSaveError)?;
+
+        // accounts left behind by members who left the server can be claimed again
+        tx.execute(
+            "DELETE FROM users
+             WHERE active=0 AND game=?1 AND variant IS ?4
+                 AND ((player_id=?2 AND instance IS ?5) OR discord_id=?3)",
+            params![
+                self.game.to_string(),
+                self.user_id,
+                discord_user_id,
+                self.variant,
+                self.instance
+            ],
+        )
+        .map_err(|_| DBSaveError)?;
 
-        let _ = conn.execute(
-            "INSERT INTO users (discord_id, game, player_id, username, country) values (?1, ?2, ?3, ?4, ?5)",
+        tx.execute(
+            "INSERT INTO users (discord_id, game, player_id, username, country, global_rank, country_rank, variant, instance)
+             values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
             params![
                 discord_user_id,
                 self.game.to_string(),
                 self.user_id,
                 self.username,
-                country.map(|s| s.to_string())
+                country.map(|s| s.to_string()),
+                self.ranks.global,
+                self.ranks.country,
+                self.variant,
+                self.instance,
             ],
-        );
+        )
+        .map_err(|_| DBSaveError)?;
 
-        Ok(())
+        tx.commit().map_err(|_| DBSaveError)
     }
 
-    pub fn from_osu(response: &str) -> Option<Self> {
-        let response = serde_json::from_str::<OsuUser>(response).ok()?;
-        let link = format!("http://osu.ppy.sh/users/{}", response.id);
-
-        let ranks = Ranks {
-            global: response.statistics.global_rank,
-            country: response.statistics.country_rank,
-        };
-
-        let playtime = if let Some(playtime) = response.statistics.play_time {
-            Some((playtime / 3600 as u64) as u32)
-        } else {
-            None
-        };
+    /// Removes the account from the member's linked accounts.
+    pub fn delete_from_database(&self, discord_user_id: u64) -> Result<(), DBSaveError> {
+        let conn = Connection::open("users.db").map_err(|_| DBSaveError)?;
 
-        Some(Self {
-            game: Game::Osu,
-            user_id: response.id,
-            username: response.username.to_string(),
-            avatar_url: response.avatar_url.to_string(),
-            country: Some(response.country.code.to_string()),
-            ranks,
-            link,
-            playtime,
-            level: None,
-        })
-    }
+        conn.execute(
+            "DELETE FROM users WHERE discord_id=?1 AND game=?2 AND variant IS ?3",
+            params![discord_user_id, self.game.to_string(), self.variant],
+        )
+        .map_err(|_| DBSaveError)?;
 
-    pub fn from_quaver(response: &str) -> Option<Self> {
-        let response = serde_json::from_str::<QuaverUserResponse>(response)
-            .ok()?
-            .user;
-        let link = format!("https://quavergame.com/user/{}", response.id);
-
-        Some(Self {
-            game: Game::Quaver,
-            user_id: response.id,
-            username: response.username.to_string(),
-            country: Some(response.country.to_string()),
-            avatar_url: response.avatar_url.to_string(),
-            ranks: response.stats_keys7.ranks,
-            link,
-            playtime: None,
-            level: None,
-        })
+        Ok(())
     }
 
-    pub fn from_tachi(user_response: &str, user_game_stats_response: &str) -> Option<Self> {
-        let user_response = serde_json::from_str::<TachiUserResponse>(user_response)
-            .ok()?
-            .body;
-
-        let user_game_stats_response =
-            serde_json::from_str::<TachiGameStatsResponse>(user_game_stats_response)
-                .ok()?
-                .body;
+    /// Records the current ranks of the account in the rank history.
+    pub fn save_rank_snapshot(
+        &self,
+        discord_user_id: u64,
+        recorded_at: u64,
+    ) -> Result<(), DBSaveError> {
+        let conn = Connection::open("users.db").map_err(|_| DBSaveError)?;
 
-        let link = format!(
-            "https://boku.tachi.ac/u/{}",
-            user_response.username_lowercase
-        );
+        conn.execute(
+            "INSERT INTO rank_history (discord_id, game, player_id, global_rank, country_rank, playtime, level, recorded_at, variant)
+             values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                discord_user_id,
+                self.game.to_string(),
+                self.user_id,
+                self.ranks.global,
+                self.ranks.country,
+                self.playtime,
+                self.level,
+                recorded_at,
+                self.variant,
+            ],
+        )
+        .map_err(|_| DBSaveError)?;
 
-        let ranks = Ranks {
-            global: Some(user_game_stats_response.ranking_data.sieglinde.ranking),
-            country: None,
-        };
+        Ok(())
+    }
 
-        let playtime = if let Some(playtime) = user_response.playtime {
-            Some((playtime / 3e6 as u64) as u32)
-        } else {
-            None
-        };
+    pub fn create_profile_embed(&self, country: &str) -> CreateEmbed {
+        let mut description = format!("**- Country:** {country}\n\n");
 
-        Some(Self {
-            game: Game::BMS,
-            user_id: user_response.id,
-            username: user_response.username,
-            country: None,
-            avatar_url: format!(
-                "https://boku.tachi.ac/api/v1/users/{}/pfp",
-                user_response.id
-            ),
-            link,
-            ranks,
-            playtime,
-            level: None,
-        })
-    }
+        if let Some(level) = self.level {
+            description += &format!("**- Level:** {level}\n\n");
+        }
 
-    pub fn from_dmjam(response: &str) -> Option<Self> {
-        let response = serde_json::from_str::<DMJamUser>(response).ok()?;
+        if let Some(rating) = self.rating {
+            description += &format!("**- Overall Rating:** {rating:.2}\n\n");
+        }
 
-        let ranks = Ranks {
-            global: Some(response.player_ranking),
-            country: None,
+        let rank = self.ranks.global.unwrap_or(0);
+        description += &match self.ranks.country {
+            Some(country_rank) => {
+                format!("**- Rank:** Global: #{rank} | Country: #{country_rank}\n\n")
+            }
+            None => format!("**- Rank:** #{rank}\n\n"),
         };
 
-        let link = format!(
-            "https://dmjam.net/player-scoreboard/{}/2",
-            response.player_code
-        );
-
-        Some(Self {
-            game: Game::DMJam,
-            user_id: response.player_code,
-            username: response.nickname,
-            avatar_url: String::new(),
-            ranks,
-            country: None,
-            link,
-            playtime: None,
-            level: Some(response.level),
-        })
-    }
-
-    pub fn create_profile_embed(&self, country: &str) -> CreateEmbed {
-        match self.game {
-            Game::Osu => Self::create_osu_embed(&self, country),
-            Game::Quaver => Self::create_quaver_embed(&self, country),
-            Game::DMJam => Self::create_dmjam_embed(&self, country),
-            Game::BMS => Self::create_bokutachi_embed(&self, country),
+        if let Some(playtime) = self.playtime {
+            description += &format!("**- Play Time:** {playtime}h\n\n");
         }
-    }
 
-    fn create_osu_embed(user: &User, country: &str) -> CreateEmbed {
-        CreateEmbed::new()
-            .title(format!("Osu profile for {}", user.username))
-            .image(user.avatar_url.clone())
-            .description(format!(
-                "**- Country:** {country}\n
-                **- Rank:** Global: #{rank} | Country: #{country_rank}\n
-                **- Play Time:** {playtime}h\n
-                [{link}]
-                ",
-                country = country,
-                rank = user.ranks.global.unwrap_or(0),
-                country_rank = user.ranks.country.unwrap_or(0),
-                playtime = user.playtime.unwrap_or(0),
-                link = user.link,
-            ))
-            .color(0xff66f0)
-    }
+        description += &format!("[{}]", self.link);
 
-    fn create_quaver_embed(user: &User, country: &str) -> CreateEmbed {
-        CreateEmbed::new()
-            .title(format!("Quaver 7k profile for {}", user.username))
-            .image(user.avatar_url.clone())
-            .description(format!(
-                "**- Country:** {country}\n
-                **- Rank:** Global: #{rank} | Country: #{country_rank}\n
-                [{link}]
-                ",
-                country = country,
-                rank = user.ranks.global.unwrap_or(0),
-                country_rank = user.ranks.country.unwrap_or(0),
-                link = user.link,
-            ))
-            .color(0xff66f0)
-    }
+        let title = self.game.variant_title(self.variant.as_deref());
 
-    fn create_bokutachi_embed(user: &User, country: &str) -> CreateEmbed {
-        CreateEmbed::new()
-            .title(format!("BMS 7k profile for {}", user.username))
-            .description(format!(
-                "**- Country:** {country}\n
-                **- Rank:** #{rank}\n
-                **- Play Time:** {playtime}h\n
-                [{link}]
-                ",
-                country = country,
-                rank = user.ranks.global.unwrap_or(0),
-                playtime = user.playtime.unwrap_or(0),
-                link = user.link,
-            ))
-            .color(0xff66f0)
-    }
+        let embed = CreateEmbed::new()
+            .title(format!("{title} profile for {}", self.username))
+            .description(description)
+            .color(0xff66f0);
 
-    fn create_dmjam_embed(user: &User, country: &str) -> CreateEmbed {
-        CreateEmbed::new()
-            .title(format!("DMJam profile for {}", user.username))
-            .description(format!(
-                "**- Country:** {country}\n
-                **- Level:** {level}\n
-                **- Rank:** #{rank}\n
-                [{link}]
-                ",
-                country = country,
-                level = user.level.unwrap_or(0),
-                rank = user.ranks.global.unwrap_or(0),
-                link = user.link,
-            ))
-            .color(0xff66f0)
+        if self.avatar_url.is_empty() {
+            embed
+        } else {
+            embed.image(self.avatar_url.clone())
+        }
     }
 }
@@ -1,12 +1,21 @@
-use serenity::builder::{CreateEmbed, EditMember, EditMessage, EditRole};
+use serenity::builder::{CreateEmbed, CreateMessage, EditMember, EditMessage, EditRole};
+use serenity::http::{Http, HttpError};
 use serenity::model::prelude::*;
 use serenity::prelude::*;
 
+use rusqlite::{Connection, params};
+
 use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher, RandomState};
 use std::ops::{Deref, DerefMut};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use crate::config::Config;
-use crate::user::User;
+use crate::game_api::{GameProviders, StoredAccount};
+use crate::ranks;
+use crate::user::{self, Game, User};
 
 pub struct VerificationInfo {
     pub id: u32,
@@ -14,6 +23,85 @@ pub struct VerificationInfo {
     pub user: User,
     pub status_message: Option<Message>,
     pub verification_message: Option<Message>,
+    pub created_at: u64,
+}
+
+/// A pending verification as stored in the database, without any of the discord or
+/// game data that has to be fetched again after a restart.
+struct PersistedVerification {
+    id: u64,
+    discord_id: u64,
+    game: String,
+    player_id: u32,
+    username: Option<String>,
+    variant: Option<String>,
+    instance: Option<String>,
+    country: Option<String>,
+    status_message: Option<(u64, u64)>,
+    verification_message: Option<(u64, u64)>,
+    created_at: u64,
+}
+
+impl PersistedVerification {
+    /// Marks the request as expired using the stored message ids, for requests that were never
+    /// restored into memory.
+    async fn expire(&self, http: &Http) {
+        if let Some((channel, message)) = self.status_message {
+            let new_status =
+                EditMessage::new().embed(expired_embed(&format!("<@{}>", self.discord_id)));
+            if let Err(e) = ChannelId::new(channel)
+                .edit_message(http, MessageId::new(message), new_status)
+                .await
+                && !is_not_found(&e)
+            {
+                eprintln!(
+                    "Could not edit status message of verification {}: {e}",
+                    self.id
+                );
+            }
+        }
+
+        if let Some((channel, message)) = self.verification_message
+            && let Err(e) = ChannelId::new(channel)
+                .delete_message(http, MessageId::new(message))
+                .await
+            && !is_not_found(&e)
+        {
+            eprintln!("Could not delete prompt of verification {}: {e}", self.id);
+        }
+    }
+}
+
+/// Why a saved verification could not be restored.
+enum RebuildError {
+    /// The member, the game or one of the messages no longer exists, so it never will be.
+    Gone(String),
+    /// Something could not be fetched right now, e.g. because the game api is down.
+    Unavailable(String),
+}
+
+/// Whether discord answered that the requested member or message doesn't exist.
+fn is_not_found(error: &SerenityError) -> bool {
+    matches!(
+        error,
+        SerenityError::Http(HttpError::UnsuccessfulRequest(response))
+            if response.status_code.as_u16() == 404
+    )
+}
+
+fn fetch_error(what: &str, error: SerenityError) -> RebuildError {
+    let message = format!("Could not get {what}: {error}");
+    if is_not_found(&error) {
+        RebuildError::Gone(message)
+    } else {
+        RebuildError::Unavailable(message)
+    }
+}
+
+fn expired_embed(name: &str) -> CreateEmbed {
+    CreateEmbed::new()
+        .title("Verification Request")
+        .description(format!("**Current status for {name}:** ⚪ Expired"))
 }
 
 impl VerificationInfo {
@@ -30,78 +118,121 @@ impl VerificationInfo {
 
         let country = crate::country_from_code(country).unwrap();
 
-        let status_message = match self.status_message {
-            Some(ref mut status_message) => status_message,
-            None => return Err("Status message has not been created".to_string()),
-        };
-
-        let verification_message = match self.verification_message {
-            Some(ref mut verification_message) => verification_message,
-            None => return Err("Verification message has not been created".to_string()),
-        };
-
-        let mut emoji_shortcode = &country.to_lowercase().replace(" ", "_");
-
-        let config = Config::load().unwrap_or_default();
-
-        if let Some(exception) = config.emoji_exceptions.get(emoji_shortcode) {
-            emoji_shortcode = exception;
+        if self.status_message.is_none() {
+            return Err("Status message has not been created".to_string());
         }
 
-        let emoji = emojis::get_by_shortcode(emoji_shortcode)
-            .ok_or(format!("Could not get emoji from country: {}", &country))?
-            .as_str();
-
-        let role_name = country.to_string() + " " + emoji;
-
-        let role = match guild.role_by_name(&role_name) {
-            Some(role) => role,
-            None => {
-                // create role if it doesn't already exist
-                let role_builder = EditRole::new().name(&role_name);
-
-                let role_builder = if guild.premium_tier >= PremiumTier::Tier2 {
-                    role_builder.unicode_emoji(Some(emoji.to_string()))
-                } else {
-                    role_builder
-                };
+        if self.verification_message.is_none() {
+            return Err("Verification message has not been created".to_string());
+        }
 
-                &guild
-                    .create_role(&ctx.http, role_builder)
-                    .await
-                    .map_err(|e| {
-                        format!(
-                            "Could not create new role: {role_name}.\n
-                            Reason: {e}"
-                        )
-                    })?
-            }
+        let discord_id = self.discord_user.user.id.get();
+        let first_verification = !user::has_linked_account(discord_id)
+            .map_err(|_| "Could not read user from database".to_string())?;
+
+        // members verifying another game keep the roles and nickname from their first one
+        let member_roles = if first_verification {
+            Some(
+                Config::load()
+                    .unwrap_or_default()
+                    .member_roles(&guild.roles)?,
+            )
+        } else {
+            None
         };
 
-        if let Err(e) = self.discord_user.add_role(&ctx.http, role).await {
-            return Err(format!("Could not add role to user: {e}"));
+        let mut changes = AppliedChanges::default();
+        if let Err(e) = self
+            .apply_changes(ctx, &guild, country, member_roles, &mut changes)
+            .await
+        {
+            let failures = changes
+                .rollback(&ctx.http, &self.discord_user, &self.user)
+                .await;
+
+            return Err(if failures.is_empty() {
+                format!("{e}, the approval was rolled back")
+            } else {
+                format!(
+                    "{e}, rolling back the approval failed as well: {}",
+                    failures.join(", ")
+                )
+            });
         }
 
-        // Save new user to database
-        if let Err(_) = self
+        // the member is verified at this point, nothing below undoes that
+        if self
             .user
-            .save_to_database(self.discord_user.user.id.get(), Some(country))
+            .save_rank_snapshot(discord_id, unix_now())
+            .is_err()
         {
-            return Err(format!("Could not save user to database"));
+            eprintln!("Could not save ranks of {}", self.user.username);
         }
 
-        let member_role = match guild.role_by_name("Member") {
-            Some(role) => role,
-            None => return Err("Member role does not exist".to_string()),
-        };
+        if let Err(e) = ranks::sync_rank_roles(
+            &ctx.http,
+            &self.discord_user,
+            self.user.game,
+            self.user.variant.as_deref(),
+            self.user.ranks.global,
+        )
+        .await
+        {
+            eprintln!("{e}");
+        }
 
-        if let Err(e) = self.discord_user.add_role(&ctx.http, member_role).await {
-            return Err(format!("Could not add role to user: {e}"));
+        if let Some(ref verification_message) = self.verification_message
+            && verification_message.delete(&ctx.http).await.is_err()
+        {
+            eprintln!("Failed to delete verification prompt");
         }
 
-        let member_settings = EditMember::new().nickname(&self.user.username);
-        if let Err(e) = self.discord_user.edit(&ctx.http, member_settings).await {
-            return Err(format!("Could not edit the users' nickname: {e}"));
+        Ok(())
+    }
+
+    /// The steps of [`VerificationInfo::apply`] that have to be undone if any of them fails,
+    /// each one is recorded in `changes` once it went through.
+    async fn apply_changes(
+        &mut self,
+        ctx: &Context,
+        guild: &PartialGuild,
+        country: &str,
+        member_roles: Option<Vec<RoleId>>,
+        changes: &mut AppliedChanges,
+    ) -> Result<(), String> {
+        let discord_id = self.discord_user.user.id.get();
+
+        self.user
+            .save_to_database(discord_id, Some(country))
+            .map_err(|_| "Could not save user to database".to_string())?;
+        changes.saved_account = true;
+
+        if let Some(member_roles) = member_roles {
+            let (country_role, created) = Self::country_role(ctx, guild, country).await?;
+            if created {
+                changes.created_role = Some(country_role);
+            }
+
+            let roles = std::iter::once(country_role)
+                .chain(member_roles)
+                .filter(|role| !self.discord_user.roles.contains(role))
+                .collect::<Vec<RoleId>>();
+
+            Self::add_member_roles(ctx, &self.discord_user, &roles).await?;
+            changes.added_roles = roles;
+
+            if Self::set_nickname(
+                ctx,
+                &self.discord_user,
+                self.user.game,
+                &self.user.username,
+                country,
+                self.user.ranks.global,
+            )
+            .await?
+            {
+                changes.previous_nickname = Some(self.discord_user.nick.clone());
+            }
         }
 
         let new_status_embed = CreateEmbed::new()
@@ -112,24 +243,111 @@ impl VerificationInfo {
             ));
 
         let new_status = EditMessage::new().embed(new_status_embed);
-        if let Err(_) = status_message.edit(&ctx.http, new_status).await {
-            return Err(format!("Could not not edit status message"));
+        if let Some(ref mut status_message) = self.status_message
+            && status_message.edit(&ctx.http, new_status).await.is_err()
+        {
+            return Err("Could not not edit status message".to_string());
         }
 
-        if let Err(_) = verification_message.delete(&ctx.http).await {
-            return Err(format!("Failed to delete verification prompt"));
+        Ok(())
+    }
+
+    async fn add_member_roles(
+        ctx: &Context,
+        member: &Member,
+        roles: &[RoleId],
+    ) -> Result<(), String> {
+        if let Err(e) = member.add_roles(&ctx.http, roles).await {
+            return Err(format!("Could not add role to user: {e}"));
         }
 
         Ok(())
     }
 
-    pub async fn deny(&mut self, ctx: &Context) -> Result<(), String> {
+    /// Sets the nickname from the configured template, unless nicknames are left alone.
+    /// Returns whether the nickname was changed.
+    async fn set_nickname(
+        ctx: &Context,
+        member: &Member,
+        game: Game,
+        username: &str,
+        country: &str,
+        rank: Option<u32>,
+    ) -> Result<bool, String> {
+        let config = Config::load().unwrap_or_default();
+        let Some(nickname) = config.nickname(game, username, country, rank) else {
+            return Ok(false);
+        };
+
+        let member_settings = EditMember::new().nickname(nickname);
+        if let Err(e) = member
+            .guild_id
+            .edit_member(&ctx.http, member.user.id, member_settings)
+            .await
+        {
+            return Err(format!("Could not edit the users' nickname: {e}"));
+        }
+
+        Ok(true)
+    }
+
+    /// Finds the role of the country, creating it if it doesn't exist yet. Returns the role
+    /// and whether it was created.
+    async fn country_role(
+        ctx: &Context,
+        guild: &PartialGuild,
+        country: &str,
+    ) -> Result<(RoleId, bool), String> {
+        let mut emoji_shortcode = &country.to_lowercase().replace(" ", "_");
+
+        let config = Config::load().unwrap_or_default();
+
+        if let Some(exception) = config.emoji_exceptions.get(emoji_shortcode) {
+            emoji_shortcode = exception;
+        }
+
+        let emoji = emojis::get_by_shortcode(emoji_shortcode)
+            .ok_or(format!("Could not get emoji from country: {}", &country))?
+            .as_str();
+
+        let role_name = country.to_string() + " " + emoji;
+
+        if let Some(role) = guild.role_by_name(&role_name) {
+            return Ok((role.id, false));
+        }
+
+        // create role if it doesn't already exist
+        let role_builder = EditRole::new().name(&role_name);
+
+        let role_builder = if guild.premium_tier >= PremiumTier::Tier2 {
+            role_builder.unicode_emoji(Some(emoji.to_string()))
+        } else {
+            role_builder
+        };
+
+        let role = guild
+            .create_role(&ctx.http, role_builder)
+            .await
+            .map_err(|e| {
+                format!(
+                    "Could not create new role: {role_name}.\n
+                    Reason: {e}"
+                )
+            })?;
+
+        Ok((role.id, true))
+    }
+
+    /// Marks the request as denied and tells the applicant why. Returns whether the reason
+    /// could be sent as a DM, if not it is still shown in the status message.
+    pub async fn deny(&mut self, ctx: &Context, reason: &str) -> Result<bool, String> {
         let new_status_embed = CreateEmbed::new()
             .title("Verification Request")
             .description(format!(
                 "**Current status for {}:** 🔴 Denied",
                 self.discord_user.user.display_name()
-            ));
+            ))
+            .field("Reason", reason, false);
 
         let new_status = EditMessage::new().embed(new_status_embed);
 
@@ -143,18 +361,154 @@ impl VerificationInfo {
             None => return Err("Verification message has not been created".to_string()),
         };
 
-        if let Err(_) = status_message.edit(&ctx.http, new_status).await {
-            return Err(format!("Could not not edit status message"));
+        if status_message.edit(&ctx.http, new_status).await.is_err() {
+            return Err("Could not not edit status message".to_string());
+        }
+
+        if verification_message.delete(&ctx.http).await.is_err() {
+            return Err("Failed to delete verification prompt".to_string());
         }
 
-        if let Err(_) = verification_message.delete(&ctx.http).await {
-            return Err(format!("Failed to delete verification prompt"));
+        let dm = CreateMessage::new().content(format!(
+            "Your verification request for {} ({}) was denied: {reason}",
+            self.user.game.title(),
+            self.user.username
+        ));
+
+        Ok(self
+            .discord_user
+            .user
+            .direct_message(&ctx.http, dm)
+            .await
+            .is_ok())
+    }
+
+    /// Marks the request as expired. Unlike [`VerificationInfo::deny`] this also handles
+    /// requests that never got past the country selection and have no messages yet.
+    pub async fn expire(&mut self, http: &Http) -> Result<(), String> {
+        let new_status_embed = expired_embed(self.discord_user.user.display_name());
+
+        if let Some(ref mut status_message) = self.status_message {
+            let new_status = EditMessage::new().embed(new_status_embed);
+            if status_message.edit(http, new_status).await.is_err() {
+                return Err("Could not not edit status message".to_string());
+            }
+        }
+
+        if let Some(ref verification_message) = self.verification_message
+            && verification_message.delete(http).await.is_err()
+        {
+            return Err("Failed to delete verification prompt".to_string());
         }
 
         Ok(())
     }
 }
 
+/// What an approval has changed so far, so that a failed approval can be undone.
+#[derive(Default)]
+struct AppliedChanges {
+    saved_account: bool,
+    created_role: Option<RoleId>,
+    added_roles: Vec<RoleId>,
+    /// The nickname the member had before, if it was changed.
+    previous_nickname: Option<Option<String>>,
+}
+
+impl AppliedChanges {
+    /// Undoes the recorded changes, returning what could not be undone.
+    async fn rollback(self, http: &Http, member: &Member, user: &User) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        if let Some(nickname) = self.previous_nickname {
+            let member_settings = EditMember::new().nickname(nickname.unwrap_or_default());
+            if let Err(e) = member
+                .guild_id
+                .edit_member(http, member.user.id, member_settings)
+                .await
+            {
+                failures.push(format!("could not restore the nickname: {e}"));
+            }
+        }
+
+        if !self.added_roles.is_empty()
+            && let Err(e) = member.remove_roles(http, &self.added_roles).await
+        {
+            failures.push(format!("could not remove the added roles: {e}"));
+        }
+
+        if let Some(role) = self.created_role
+            && let Err(e) = member.guild_id.delete_role(http, role).await
+        {
+            failures.push(format!("could not delete the created country role: {e}"));
+        }
+
+        if self.saved_account && user.delete_from_database(member.user.id.get()).is_err() {
+            failures.push("could not delete the saved account".to_string());
+        }
+
+        failures
+    }
+}
+
+/// Gives a member who rejoined the server the roles and nickname of their first verification
+/// back. Returns whether they had any linked accounts to restore.
+pub async fn restore_member(ctx: &Context, member: &Member) -> Result<bool, String> {
+    let discord_id = member.user.id.get();
+
+    let restored = user::set_active(discord_id, true)
+        .map_err(|_| "Could not update user in database".to_string())?;
+    if restored == 0 {
+        return Ok(false);
+    }
+
+    let (game, username, country, rank) = {
+        let conn = Connection::open("users.db").map_err(|_| "Database failure")?;
+        conn.query_row(
+            "SELECT game, username, country, global_rank FROM users
+            WHERE discord_id=?1 ORDER BY rowid LIMIT 1",
+            [discord_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<u32>>(3)?,
+                ))
+            },
+        )
+        .map_err(|_| "Database failure")?
+    };
+    let game = Game::from_str(&game).map_err(|_| format!("{game} is not a valid game"))?;
+
+    let guild = member
+        .guild_id
+        .to_partial_guild(&ctx.http)
+        .await
+        .map_err(|_| "Could not get server from id".to_string())?;
+
+    let member_roles = Config::load()
+        .unwrap_or_default()
+        .member_roles(&guild.roles)?;
+
+    let (country_role, _) = VerificationInfo::country_role(ctx, &guild, &country).await?;
+    let roles = std::iter::once(country_role)
+        .chain(member_roles)
+        .collect::<Vec<RoleId>>();
+
+    VerificationInfo::add_member_roles(ctx, member, &roles).await?;
+    VerificationInfo::set_nickname(ctx, member, game, &username, &country, rank).await?;
+
+    Ok(true)
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
 #[derive(Default)]
 pub struct PendingVerifications {
     current_id: u64,
@@ -165,11 +519,293 @@ impl PendingVerifications {
     pub fn use_current_id(&mut self) -> u64 {
         let id = self.current_id;
         self.current_id += 1;
-        id.clone()
+        id
+    }
+
+    /// Writes the verification with the given id to the database so that it survives a restart.
+    pub fn save(&self, id: u64) -> Result<(), String> {
+        let verification = self
+            .get(&id)
+            .ok_or(format!("No pending verification with id {id}"))?;
+
+        let message_ids =
+            |message: &Option<Message>| message.as_ref().map(|m| (m.channel_id.get(), m.id.get()));
+        let status_message = message_ids(&verification.status_message);
+        let verification_message = message_ids(&verification.verification_message);
+
+        let conn = Connection::open("users.db").map_err(|_| "Database failure".to_string())?;
+
+        conn.execute(
+            "INSERT OR REPLACE INTO pending_verifications (
+                 id, discord_id, game, player_id, country,
+                 status_channel_id, status_message_id,
+                 verification_channel_id, verification_message_id, created_at, username, variant, instance
+             ) values (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                id,
+                verification.discord_user.user.id.get(),
+                verification.user.game.to_string(),
+                verification.user.user_id,
+                verification.user.country,
+                status_message.map(|(channel, _)| channel),
+                status_message.map(|(_, message)| message),
+                verification_message.map(|(channel, _)| channel),
+                verification_message.map(|(_, message)| message),
+                verification.created_at,
+                verification.user.username,
+                verification.user.variant,
+                verification.user.instance,
+            ],
+        )
+        .map_err(|e| format!("Could not save pending verification: {e}"))?;
+
+        Ok(())
+    }
+
+    /// Removes a verification from both memory and the database.
+    pub fn discard(&mut self, id: u64) -> Option<VerificationInfo> {
+        Self::delete_saved(id);
+        self.remove(&id)
+    }
+
+    fn delete_saved(id: u64) {
+        if let Ok(conn) = Connection::open("users.db")
+            && let Err(e) = conn.execute("DELETE FROM pending_verifications WHERE id=?1", [id])
+        {
+            eprintln!("Could not delete pending verification {id}: {e}");
+        }
+    }
+
+    /// Loads the verifications stored in the database, fetching the discord member, the game
+    /// profile and the bot's messages again. Requests whose member or messages are gone are
+    /// discarded, ones that only failed to load for now are retried by
+    /// [`PendingVerifications::reload`] when an admin acts on them.
+    pub async fn load_saved(
+        ctx: &Context,
+        guild_id: &GuildId,
+        providers: &GameProviders,
+    ) -> Result<Vec<(u64, VerificationInfo)>, String> {
+        let rows = Self::saved_rows()?;
+
+        let mut verifications = Vec::new();
+        for row in rows {
+            match Self::rebuild_saved(ctx, guild_id, providers, &row).await {
+                Ok(Some(verification)) => verifications.push((row.id, verification)),
+                Ok(None) => {}
+                Err(e) => eprintln!("Could not restore pending verification {}: {e}", row.id),
+            }
+        }
+
+        Ok(verifications)
+    }
+
+    /// Loads a saved verification that is not in memory, like one whose game profile could
+    /// not be fetched at startup. Does nothing if there is no such request (anymore).
+    pub async fn reload(
+        &mut self,
+        ctx: &Context,
+        guild_id: &GuildId,
+        providers: &GameProviders,
+        id: u64,
+    ) -> Result<(), String> {
+        if self.contains_key(&id) {
+            return Ok(());
+        }
+
+        let Some(row) = Self::saved_rows()?.into_iter().find(|row| row.id == id) else {
+            return Ok(());
+        };
+
+        if let Some(verification) = Self::rebuild_saved(ctx, guild_id, providers, &row).await? {
+            self.verifications.insert(id, verification);
+        }
+
+        Ok(())
+    }
+
+    /// Rebuilds a saved verification, discarding it when it can never be rebuilt.
+    async fn rebuild_saved(
+        ctx: &Context,
+        guild_id: &GuildId,
+        providers: &GameProviders,
+        row: &PersistedVerification,
+    ) -> Result<Option<VerificationInfo>, String> {
+        match Self::rebuild(ctx, guild_id, providers, row).await {
+            Ok(verification) => Ok(Some(verification)),
+            Err(RebuildError::Gone(e)) => {
+                eprintln!("Discarding pending verification {}: {e}", row.id);
+                row.expire(&ctx.http).await;
+                Self::delete_saved(row.id);
+                Ok(None)
+            }
+            Err(RebuildError::Unavailable(e)) => Err(e),
+        }
+    }
+
+    /// Adds verifications returned by [`PendingVerifications::load_saved`] without replacing
+    /// requests that were made since, and moves the id counter past every stored id.
+    pub fn restore(&mut self, verifications: Vec<(u64, VerificationInfo)>) {
+        for (id, verification) in verifications {
+            self.verifications.entry(id).or_insert(verification);
+        }
+
+        let next_id = Connection::open("users.db")
+            .and_then(|conn| {
+                conn.query_row(
+                    "SELECT COALESCE(MAX(id) + 1, 0) FROM pending_verifications",
+                    [],
+                    |row| row.get::<_, u64>(0),
+                )
+            })
+            .unwrap_or(0);
+
+        self.current_id = self.current_id.max(next_id);
+    }
+
+    /// Removes every request older than `ttl` seconds and returns them.
+    pub fn take_expired(&mut self, ttl: u64) -> Vec<VerificationInfo> {
+        let now = unix_now();
+        let expired: Vec<u64> = self
+            .iter()
+            .filter(|(_, verification)| now.saturating_sub(verification.created_at) >= ttl)
+            .map(|(id, _)| *id)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|id| self.discard(id))
+            .collect()
+    }
+
+    /// Removes the saved requests older than `ttl` seconds that were never restored into
+    /// memory and returns them.
+    fn take_stale_rows(&self, ttl: u64) -> Vec<PersistedVerification> {
+        let now = unix_now();
+        let stale = Self::saved_rows()
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|row| now.saturating_sub(row.created_at) >= ttl)
+            .filter(|row| !self.contains_key(&row.id))
+            .collect::<Vec<_>>();
+
+        for row in &stale {
+            Self::delete_saved(row.id);
+        }
+
+        stale
+    }
+
+    fn saved_rows() -> Result<Vec<PersistedVerification>, String> {
+        let conn = Connection::open("users.db").map_err(|_| "Database failure".to_string())?;
+
+        let mut stmt = conn
+            .prepare(
+                "SELECT id, discord_id, game, player_id, country,
+                     status_channel_id, status_message_id,
+                     verification_channel_id, verification_message_id, created_at, username, variant, instance
+                 FROM pending_verifications",
+            )
+            .map_err(|_| "Database failure".to_string())?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                let message_ids = |channel: usize, message: usize| -> rusqlite::Result<_> {
+                    Ok(row
+                        .get::<_, Option<u64>>(channel)?
+                        .zip(row.get::<_, Option<u64>>(message)?))
+                };
+
+                Ok(PersistedVerification {
+                    id: row.get(0)?,
+                    discord_id: row.get(1)?,
+                    game: row.get(2)?,
+                    player_id: row.get(3)?,
+                    country: row.get(4)?,
+                    status_message: message_ids(5, 6)?,
+                    verification_message: message_ids(7, 8)?,
+                    created_at: row.get(9)?,
+                    username: row.get(10)?,
+                    variant: row.get(11)?,
+                    instance: row.get(12)?,
+                })
+            })
+            .map_err(|_| "Database failure".to_string())?;
+
+        Ok(rows.flatten().collect())
+    }
+
+    async fn rebuild(
+        ctx: &Context,
+        guild_id: &GuildId,
+        providers: &GameProviders,
+        row: &PersistedVerification,
+    ) -> Result<VerificationInfo, RebuildError> {
+        let discord_user = guild_id
+            .member(&ctx.http, UserId::new(row.discord_id))
+            .await
+            .map_err(|e| fetch_error("member", e))?;
+
+        let game = Game::from_str(&row.game)
+            .map_err(|_| RebuildError::Gone(format!("Unknown game {}", row.game)))?;
+        let provider = providers
+            .get(game)
+            .ok_or(RebuildError::Gone(format!("No provider for {game}")))?;
+
+        // requests saved before usernames were stored fall back to the player id
+        let identifier = match row.username {
+            Some(ref username) => provider.stored_identifier(&StoredAccount {
+                player_id: row.player_id,
+                username,
+                variant: row.variant.as_deref(),
+                instance: row.instance.as_deref(),
+            }),
+            None => row.player_id.to_string(),
+        };
+
+        let mut user = provider
+            .get_user(&identifier)
+            .await
+            .ok_or(RebuildError::Unavailable(
+                "Could not fetch game profile".to_string(),
+            ))?;
+
+        // keep the country the user picked instead of the one reported by the game
+        if row.country.is_some() {
+            user.country = row.country.clone();
+        }
+
+        let status_message = match row.status_message {
+            Some((channel, message)) => Some(
+                ChannelId::new(channel)
+                    .message(&ctx.http, MessageId::new(message))
+                    .await
+                    .map_err(|e| fetch_error("status message", e))?,
+            ),
+            None => None,
+        };
+
+        let verification_message = match row.verification_message {
+            Some((channel, message)) => Some(
+                ChannelId::new(channel)
+                    .message(&ctx.http, MessageId::new(message))
+                    .await
+                    .map_err(|e| fetch_error("verification message", e))?,
+            ),
+            None => None,
+        };
+
+        Ok(VerificationInfo {
+            id: row.id as u32,
+            discord_user,
+            user,
+            status_message,
+            verification_message,
+            created_at: row.created_at,
+        })
     }
 }
 
-impl<'a> Deref for PendingVerifications {
+impl Deref for PendingVerifications {
     type Target = HashMap<u64, VerificationInfo>;
 
     fn deref(&self) -> &Self::Target {
@@ -177,7 +813,7 @@ impl<'a> Deref for PendingVerifications {
     }
 }
 
-impl<'a> DerefMut for PendingVerifications {
+impl DerefMut for PendingVerifications {
     fn deref_mut(&mut self) -> &mut Self::Target {
         &mut self.verifications
     }
@@ -186,3 +822,105 @@ impl<'a> DerefMut for PendingVerifications {
 impl TypeMapKey for PendingVerifications {
     type Value = PendingVerifications;
 }
+
+/// A token a member has been asked to place on their game profile to prove they own it.
+pub struct OwnershipChallenge {
+    pub game: Game,
+    pub player_id: u32,
+    pub token: String,
+}
+
+impl OwnershipChallenge {
+    pub fn new(discord_id: u64, user: &User) -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(discord_id);
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_nanos())
+                .unwrap_or(0),
+        );
+
+        Self {
+            game: user.game,
+            player_id: user.user_id,
+            token: format!("7KG-{:08X}", hasher.finish() as u32),
+        }
+    }
+
+    pub fn is_for(&self, user: &User) -> bool {
+        self.game == user.game && self.player_id == user.user_id
+    }
+
+    pub fn is_solved_by(&self, user: &User) -> bool {
+        self.is_for(user)
+            && user
+                .profile_text
+                .as_ref()
+                .is_some_and(|text| text.to_lowercase().contains(&self.token.to_lowercase()))
+    }
+}
+
+/// Ownership challenges that have been issued but not yet solved, by discord id.
+#[derive(Default)]
+pub struct OwnershipChallenges(HashMap<u64, OwnershipChallenge>);
+
+impl Deref for OwnershipChallenges {
+    type Target = HashMap<u64, OwnershipChallenge>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for OwnershipChallenges {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl TypeMapKey for OwnershipChallenges {
+    type Value = OwnershipChallenges;
+}
+
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Periodically expires pending verifications older than the configured ttl.
+pub fn spawn_expiry_task(data: Arc<RwLock<TypeMap>>, http: Arc<Http>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+
+            let ttl = Config::load().unwrap_or_default().verification_ttl_secs();
+
+            // take the requests out first so the lock is not held during the discord calls
+            let expired = {
+                let mut data = data.write().await;
+                match data.get_mut::<PendingVerifications>() {
+                    Some(verifications) => verifications.take_expired(ttl),
+                    None => continue,
+                }
+            };
+
+            for mut verification in expired {
+                if let Err(e) = verification.expire(&http).await {
+                    eprintln!("Could not expire verification {}: {e}", verification.id);
+                }
+            }
+
+            // requests that could not be restored after a restart only exist in the database
+            let stale = {
+                let data = data.read().await;
+                match data.get::<PendingVerifications>() {
+                    Some(verifications) => verifications.take_stale_rows(ttl),
+                    None => continue,
+                }
+            };
+
+            for row in stale {
+                row.expire(&http).await;
+            }
+        }
+    });
+}